@@ -1,10 +1,13 @@
 use batl::resource::{self as batlres, BatlRc};
-use batl::resource::tomlconfig::{TomlConfig, write_toml};
+use batl::resource::batlrc::{AnyBatlRc, DEFAULT_REGISTRY};
+use batl::resource::tomlconfig::{TomlConfig, write_toml, write_toml_private};
+use colored::*;
 use crate::output::success;
 use crate::utils::UtilityError;
 use std::collections::HashMap;
 use std::env::current_dir;
 
+pub mod auth;
 pub mod workspace;
 pub mod link;
 pub mod repository;
@@ -98,30 +101,126 @@ pub fn cmd_upgrade() -> Result<(), UtilityError> {
 		success("Added gen folder");
 	}
 
-	if batl::system::batlrc().is_none() {
-		let batlrc = BatlRc::default();
-	
-		write_toml(&batl::system::batlrc_path().expect("Nonsensical already checked for root"), &batlrc)?;
+	match batl::system::any_batlrc() {
+		None => {
+			let batlrc = BatlRc::default();
+		
+			write_toml(&batl::system::batlrc_path().expect("Nonsensical already checked for root"), &batlrc)?;
+
+			success("Added batlrc toml");
+		},
+		Some(batlrc @ AnyBatlRc::V0_2_1(_)) => {
+			if let Some(key) = batlrc.legacy_credentials() {
+				let mut credentials = batl::system::credentials().unwrap_or_default();
+				credentials.tokens.entry(DEFAULT_REGISTRY.to_owned()).or_insert(key);
+
+				write_toml_private(&batl::system::credentials_path().expect("Nonsensical already checked for root"), &credentials)?;
+
+				success("Moved API key to credentials file");
+			}
+
+			write_toml(&batl::system::batlrc_path().expect("Nonsensical already checked for root"), &BatlRc::from(batlrc))?;
+
+			success("Upgraded batlrc toml");
+		},
+		Some(_) => ()
+	}
+
+	Ok(())
+}
+
+pub fn cmd_logs(last: bool, name: Option<String>) -> Result<(), UtilityError> {
+	let mut runs = batl::resource::history::runs()?;
+
+	// An exact id shows that run, anything else filters by
+	// repository or link
+	if let Some(run) = name.as_ref().and_then(|name| runs.iter().find(|run| &run.id == name)) {
+		return print_run(run);
+	}
 
-		success("Added batlrc toml");
+	if let Some(name) = &name {
+		runs.retain(|run| &run.record.target == name);
+	}
+
+	if last {
+		let run = runs.last()
+			.ok_or(UtilityError::ResourceDoesNotExist("Run log".to_string()))?;
+
+		return print_run(run);
+	}
+
+	for run in &runs {
+		let status = if run.record.success { "OK".green() } else { "ERR".red() };
+		let what = run.record.script.as_ref().or(run.record.command.as_ref()).cloned().unwrap_or_default();
+
+		println!(
+			"[{}] {}\t{}\t{}\t{}\t{}",
+			status,
+			format_timestamp(run.record.started),
+			run.record.target,
+			what,
+			format_duration(run.record.duration),
+			run.id
+		);
 	}
 
 	Ok(())
 }
 
-pub fn cmd_auth() -> Result<(), UtilityError> {
-	let mut key_prompt = dialoguer::Input::new();
+/// Print the details and output of a single run
+fn print_run(run: &batl::resource::history::LoggedRun) -> Result<(), UtilityError> {
+	let record = &run.record;
 
-	let api_key: String = key_prompt.with_prompt("API key").interact()?;
+	println!("Run:\t\t{}", run.id);
+	println!("Target:\t\t{}", record.target);
 
-	let mut batlrc = batl::system::batlrc()
-		.ok_or(UtilityError::ResourceDoesNotExist("BatlRc".to_string()))?;
+	if let Some(script) = &record.script {
+		println!("Script:\t\t{}", script);
+	}
 
-	batlrc.api.credentials = api_key;
+	if let Some(command) = &record.command {
+		println!("Command:\t{}", command);
+	}
 
-	write_toml(&batl::system::batlrc_path().expect("Nonsensical just read batlrc"), &batlrc)?;
+	println!("Started:\t{}", format_timestamp(record.started));
+	println!("Duration:\t{}", format_duration(record.duration));
+	println!("Status:\t\t{}", record.status);
+	println!();
 
-	success("Added new API key");
+	print!("{}", std::fs::read_to_string(&run.output)?);
 
 	Ok(())
 }
+
+/// Format seconds since the unix epoch as a UTC date
+/// and time
+fn format_timestamp(secs: u64) -> String {
+	let days = (secs / 86400) as i64;
+	let time = secs % 86400;
+
+	// Convert days since the epoch into a civil date
+	let z = days + 719_468;
+	let era = z.div_euclid(146_097);
+	let doe = z.rem_euclid(146_097);
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = yoe + era * 400 + i64::from(month <= 2);
+
+	format!(
+		"{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+		year, month, day,
+		time / 3600, time % 3600 / 60, time % 60
+	)
+}
+
+/// Format a duration in milliseconds
+fn format_duration(millis: u64) -> String {
+	if millis < 1000 {
+		format!("{}ms", millis)
+	} else {
+		format!("{:.1}s", millis as f64 / 1000.0)
+	}
+}
@@ -0,0 +1,137 @@
+use batl::resource::tomlconfig::write_toml_private;
+use clap::Subcommand;
+use crate::output::*;
+use crate::utils::UtilityError;
+
+
+#[derive(Subcommand)]
+pub enum Commands {
+	Login {
+		#[arg(long = "registry")]
+		registry: Option<String>
+	},
+	Logout {
+		#[arg(long = "registry")]
+		registry: Option<String>
+	},
+	Status
+}
+
+impl Default for Commands {
+	fn default() -> Self {
+		Self::Login { registry: None }
+	}
+}
+
+pub fn run(cmd: Commands) -> Result<(), UtilityError> {
+	match cmd {
+		Commands::Login { registry } => {
+			cmd_login(registry)
+		},
+		Commands::Logout { registry } => {
+			cmd_logout(registry)
+		},
+		Commands::Status => {
+			cmd_status()
+		}
+	}
+}
+
+/// Resolve a registry name, falling back to the default
+/// registry in the batlrc
+pub fn registry_name(registry: Option<String>) -> Result<String, UtilityError> {
+	if let Some(registry) = registry {
+		return Ok(registry);
+	}
+
+	let batlrc = batl::system::batlrc()
+		.ok_or(UtilityError::ResourceDoesNotExist("BatlRc".to_string()))?;
+
+	Ok(batlrc.api.registry)
+}
+
+fn cmd_login(registry: Option<String>) -> Result<(), UtilityError> {
+	let registry = registry_name(registry)?;
+
+	let batlrc = batl::system::batlrc()
+		.ok_or(UtilityError::ResourceDoesNotExist("BatlRc".to_string()))?;
+
+	if batlrc.registry(&registry).is_none() {
+		return Err(UtilityError::ResourceDoesNotExist(format!("Registry {}", registry)));
+	}
+
+	let mut key_prompt = dialoguer::Password::new();
+
+	let api_key: String = key_prompt.with_prompt("API key").interact()?;
+
+	let mut credentials = batl::system::credentials().unwrap_or_default();
+	credentials.tokens.insert(registry.clone(), api_key);
+
+	write_toml_private(&batl::system::credentials_path().expect("Nonsensical just read batlrc"), &credentials)?;
+
+	success(&format!("Added new API key for registry {}", registry));
+
+	Ok(())
+}
+
+fn cmd_logout(registry: Option<String>) -> Result<(), UtilityError> {
+	let registry = registry_name(registry)?;
+
+	let mut credentials = batl::system::credentials()
+		.ok_or(UtilityError::NotAuthenticated(registry.clone()))?;
+
+	if credentials.tokens.remove(&registry).is_none() {
+		return Err(UtilityError::NotAuthenticated(registry));
+	}
+
+	write_toml_private(&batl::system::credentials_path().expect("Nonsensical just read credentials"), &credentials)?;
+
+	success(&format!("Removed API key for registry {}", registry));
+
+	Ok(())
+}
+
+fn cmd_status() -> Result<(), UtilityError> {
+	let batlrc = batl::system::batlrc()
+		.ok_or(UtilityError::ResourceDoesNotExist("BatlRc".to_string()))?;
+
+	let credentials = batl::system::credentials().unwrap_or_default();
+
+	let mut registries: Vec<String> = batlrc.registries.clone()
+		.unwrap_or_default()
+		.into_keys()
+		.chain(credentials.tokens.keys().cloned())
+		.chain(std::iter::once(batlrc.api.registry.clone()))
+		.collect();
+
+	registries.sort();
+	registries.dedup();
+
+	for registry in registries {
+		let token_var = batl::system::registry_token_var(&registry);
+		let is_default = registry == batlrc.api.registry;
+
+		let source = if std::env::var(&token_var).is_ok() {
+			token_var
+		} else if is_default && std::env::var("BATL_TOKEN").is_ok() {
+			"BATL_TOKEN".to_string()
+		} else if is_default && !credentials.tokens.contains_key(&registry) {
+			"legacy batlrc, run batl upgrade".to_string()
+		} else {
+			"credentials file".to_string()
+		};
+
+		let status = if batl::system::registry_token(&registry).is_some() {
+			format!("logged in ({})", source)
+		} else {
+			"not logged in".to_string()
+		};
+
+		let url = batlrc.registry(&registry)
+			.map_or_else(|| "<unconfigured>".to_string(), |r| r.url);
+
+		println!("{}{}:\t\t{}\t{}", registry, if is_default { " (default)" } else { "" }, url, status);
+	}
+
+	Ok(())
+}
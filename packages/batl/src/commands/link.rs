@@ -1,8 +1,15 @@
 use batl::resource::{Repository, Resource, Workspace};
+use batl::resource::history::RunLog;
+use batl::resource::link::LinkMode;
+use batl::resource::script::ScriptHost;
 use clap::{Subcommand, ValueEnum};
 use crate::utils::{UtilityError, BATL_LINK_REGEX, BATL_NAME_REGEX};
 use crate::output::*;
+use crate::scripts::RunOptions;
+use crate::watch::{WatchArgs, WatchRoot, Watcher};
+use std::collections::{HashMap, HashSet};
 use std::env::current_dir;
+use std::path::PathBuf;
 
 #[derive(Subcommand)]
 pub enum Commands {
@@ -15,20 +22,45 @@ pub enum Commands {
 	Init {
 		#[arg(short = 'n', long = "name")]
 		name: Option<String>,
+		#[arg(long = "mode", default_value = "symlink")]
+		mode: InitMode,
+		#[arg(long = "branch")]
+		branch: Option<String>,
+		#[arg(long = "path")]
+		path: Option<PathBuf>,
 		repo: String
 	},
 	Delete {
 		name: String
 	},
+	Sync {
+		name: Option<String>
+	},
 	Run {
 		name: String,
 		#[arg(last = true)]
 		args: Vec<String>
 	},
 	Exec {
-		#[arg(short = 'n')]
+		#[arg(short = 'n', conflicts_with = "all")]
 		name: Option<String>,
-		script: String
+		#[arg(long = "all", requires = "script")]
+		all: bool,
+		#[arg(long = "filter", requires = "all")]
+		filter: Option<String>,
+		#[arg(short = 'j', long = "jobs", requires = "all")]
+		jobs: Option<usize>,
+		#[arg(long = "affected", requires = "all")]
+		affected: bool,
+		/// Revision to compare against, defaults to where each
+		/// link's branch forked from its upstream
+		#[arg(long = "since", requires = "affected")]
+		since: Option<String>,
+		script: Option<String>,
+		#[command(flatten)]
+		watch: WatchArgs,
+		#[arg(last = true)]
+		args: Vec<String>
 	}
 }
 
@@ -40,17 +72,23 @@ pub fn run(cmd: Commands) -> Result<(), UtilityError> {
 		Commands::Stats { name, get } => {
 			cmd_stats(name, get)
 		},
-		Commands::Init { name, repo } => {
-			cmd_init(name, repo)
+		Commands::Init { name, mode, branch, path, repo } => {
+			cmd_init(name, mode, branch, path, repo)
 		},
 		Commands::Delete { name } => {
 			cmd_delete(name)
 		},
+		Commands::Sync { name } => {
+			cmd_sync(name)
+		},
 		Commands::Run { name, args } => {
 			cmd_run(name, args)
 		},
-		Commands::Exec { name, script } => {
-			cmd_exec(name, script)
+		Commands::Exec { all: true, filter, jobs, affected, since, script, watch, args, .. } => {
+			cmd_exec_all(filter, jobs, affected, since, script.unwrap_or_default(), watch, args)
+		},
+		Commands::Exec { name, script, watch, args, .. } => {
+			cmd_exec(name, script, watch, args)
 		}
 	}
 }
@@ -72,7 +110,8 @@ fn cmd_ls() -> Result<(), UtilityError> {
 #[clap(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum StatsGet {
 	Name,
-	Repository
+	Repository,
+	Path
 }
 
 fn cmd_stats(name: String, get: Option<StatsGet>) -> Result<(), UtilityError> {
@@ -83,38 +122,81 @@ fn cmd_stats(name: String, get: Option<StatsGet>) -> Result<(), UtilityError> {
 		.ok_or(UtilityError::LinkNotFound)?;
 	
 	let path = repository.path();
+	let link_path = workspace.link_config(&name).and_then(|link| link.path);
 
 	match get {
 		None => {
 			println!("Link: {}", name);
 			println!("Repository: {}", path.display());
+
+			if let Some(link_path) = &link_path {
+				println!("Path: {}", link_path.display());
+			}
 		},
 		Some(StatsGet::Name) => println!("{name}"),
-		Some(StatsGet::Repository) => println!("{}", path.display())
+		Some(StatsGet::Repository) => println!("{}", path.display()),
+		Some(StatsGet::Path) => println!("{}", link_path.map_or_else(|| path.to_path_buf(), |link_path| path.join(link_path)).display())
 	}
 
 	Ok(())
 }
 
-fn cmd_init(name: Option<String>, repo: String) -> Result<(), UtilityError> {
+#[derive(Clone, ValueEnum)]
+pub enum InitMode {
+	Symlink,
+	Worktree,
+	Copy,
+	HardlinkTree,
+	Readonly
+}
+
+fn cmd_init(name: Option<String>, mode: InitMode, branch: Option<String>, path: Option<PathBuf>, repo: String) -> Result<(), UtilityError> {
 	if !BATL_NAME_REGEX.is_match(&repo) {
 		return Err(UtilityError::InvalidName(repo));
 	}
 
-	// TODO: Make random string
-	let name = name.unwrap_or_else(|| unimplemented!());
+	let repo = Repository::load(repo.as_str().into())?
+		.ok_or(UtilityError::ResourceDoesNotExist(format!("Repository {}", repo)))?;
+
+	let mut workspace = Workspace::locate_then_load(&current_dir()?)?
+		.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
+
+	let name = name.unwrap_or_else(|| default_name(&workspace, &repo.config().name.to_string()));
 
 	if !BATL_LINK_REGEX.is_match(&name) {
 		return Err(UtilityError::InvalidName(name));
 	}
 
-	let repo = Repository::load(repo.as_str().into())?
-		.ok_or(UtilityError::ResourceDoesNotExist(format!("Repository {}", repo)))?;
+	let mode = match mode {
+		InitMode::Symlink => LinkMode::Symlink,
+		InitMode::Worktree => LinkMode::Worktree {
+			branch: branch.unwrap_or_else(|| format!("batl/{}/{}", workspace.config().name, name))
+		},
+		InitMode::Copy => LinkMode::Copy,
+		InitMode::HardlinkTree => LinkMode::HardlinkTree,
+		InitMode::Readonly => LinkMode::Readonly
+	};
 
-	let mut workspace = Workspace::locate_then_load(&current_dir()?)?
-		.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
+	if let (LinkMode::Worktree { .. }, None) = (&mode, repo.git_checkout()) {
+		return Err(UtilityError::NoGitCheckout(repo.config().name.to_string()));
+	}
+
+	if let (LinkMode::Worktree { .. } | LinkMode::Readonly, Some(path)) = (&mode, &path) {
+		return Err(UtilityError::InvalidPath(format!(
+			"{}, only symlink, copy and hardlink-tree links can point inside a repository",
+			path.display()
+		)));
+	}
 
-	workspace.create_link(&name, &repo)?;
+	if mode == LinkMode::Readonly && repo.archive().is_none() {
+		return Err(UtilityError::ResourceDoesNotExist(format!(
+			"Archive for {}, run `batl repository archive {}` first",
+			repo.config().name,
+			repo.config().name
+		)));
+	}
+
+	workspace.create_link_with(&name, &repo, mode, path)?;
 
 	success(&format!("Initialized link {}", name));
 
@@ -132,6 +214,39 @@ fn cmd_delete(name: String) -> Result<(), UtilityError> {
 	Ok(())
 }
 
+fn cmd_sync(name: Option<String>) -> Result<(), UtilityError> {
+	let workspace = Workspace::locate_then_load(&current_dir()?)?
+		.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
+
+	let mut names: Vec<String> = match name {
+		Some(name) => {
+			if workspace.link_config(&name).is_none() {
+				return Err(UtilityError::LinkNotFound);
+			}
+
+			vec![name]
+		},
+		None => workspace.links().into_keys().collect()
+	};
+
+	names.sort();
+
+	let mut synced = 0;
+
+	for name in names {
+		if workspace.sync_link(&name)? {
+			success(&format!("Synced link {}", name));
+			synced += 1;
+		}
+	}
+
+	if synced == 0 {
+		info("No links to sync");
+	}
+
+	Ok(())
+}
+
 fn cmd_run(name: String, args: Vec<String>) -> Result<(), UtilityError> {
 	let workspace = Workspace::locate_then_load(&current_dir()?)?
 		.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
@@ -141,13 +256,25 @@ fn cmd_run(name: String, args: Vec<String>) -> Result<(), UtilityError> {
 
 	info(&format!("Running command for link {}\n", name));
 
-	let status = std::process::Command::new(args.first().unwrap())
-		.current_dir(repository.path())
-		.args(args.iter().skip(1))
-		.status()?;
+	let cwd = workspace.link_cwd(&name).unwrap_or_else(|| repository.path().to_path_buf());
+
+	let mut process = std::process::Command::new(args.first().unwrap());
+	process
+		.current_dir(cwd)
+		.envs(crate::scripts::script_env(&repository, Some(&workspace)))
+		.args(args.iter().skip(1));
+
+	let log = RunLog::create(name.clone(), None, Some(shell_words::join(&args)))?;
+	let failure = crate::scripts::run_process(&mut process, None, log.as_ref(), None)?;
+
+	if let Some(log) = log {
+		let status = failure.as_ref().map_or_else(|| "Success".to_string(), ToString::to_string);
+
+		log.finish(failure.is_none(), status)?;
+	}
 
-	if !status.success() {
-		return Err(UtilityError::ScriptError(format!("Exit code {}", status.code().unwrap_or(0))))
+	if let Some(failure) = failure {
+		return Err(UtilityError::ScriptFailed(name, failure))
 	}
 
 	println!();
@@ -156,35 +283,246 @@ fn cmd_run(name: String, args: Vec<String>) -> Result<(), UtilityError> {
 	Ok(())
 }
 
-fn cmd_exec(name: Option<String>, script: String) -> Result<(), UtilityError> {
-	let repository = match &name {
+/// Find the workspace link the current folder is in, if
+/// any. The shell's own path is preferred, since the
+/// current folder of a symlinked link resolves to the
+/// repository it points at.
+fn locate_link() -> Result<Option<(Workspace, String)>, UtilityError> {
+	let cwd = current_dir()?;
+	let cwd = std::env::var_os("PWD")
+		.map(PathBuf::from)
+		.filter(|pwd| std::fs::canonicalize(pwd).ok() == std::fs::canonicalize(&cwd).ok())
+		.unwrap_or(cwd);
+
+	let Some(workspace) = Workspace::locate_then_load(&cwd)? else {
+		return Ok(None);
+	};
+
+	let link = cwd.strip_prefix(workspace.path())
+		.ok()
+		.and_then(|relative| relative.components().next())
+		.map(|component| component.as_os_str().to_string_lossy().to_string())
+		.filter(|link| workspace.link_config(link).is_some());
+
+	Ok(link.map(|link| (workspace, link)))
+}
+
+/// Pick a free link name for a repository. This is the
+/// last component of the repository name, then names
+/// qualified by more of its parents, such as `b-c` for
+/// `a/b/c`, and finally the last component with a number.
+pub fn default_name(workspace: &Workspace, repository: &str) -> String {
+	let links = workspace.links();
+
+	free_name(repository, |name| !links.contains_key(name) && !workspace.path().join(name).exists())
+}
+
+/// Pick the first name for a repository that `is_free`
+/// accepts, in the order described for `default_name`
+fn free_name(repository: &str, is_free: impl Fn(&str) -> bool) -> String {
+	let components: Vec<&str> = repository.split('/').collect();
+
+	for start in (0..components.len()).rev() {
+		let name = components[start..].join("-");
+
+		if is_free(&name) {
+			return name;
+		}
+	}
+
+	let last = components.last().copied().unwrap_or("link");
+
+	(2..)
+		.map(|suffix| format!("{}-{}", last, suffix))
+		.find(|name| is_free(name))
+		.unwrap_or_default()
+}
+
+fn cmd_exec(name: Option<String>, script: Option<String>, watch: WatchArgs, args: Vec<String>) -> Result<(), UtilityError> {
+	let (repository, workspace, name) = match name {
 		Some(val) => {
 			let workspace = Workspace::locate_then_load(&current_dir()?)?
 				.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
 
-			workspace.link(val)
+			(workspace.link(&val), Some(workspace), Some(val))
 		},
-		None => Repository::locate_then_load(&current_dir()?)?
-	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+		None => match locate_link()? {
+			Some((workspace, link)) => (workspace.link(&link), Some(workspace), Some(link)),
+			None => (Repository::locate_then_load(&current_dir()?)?, None, None)
+		}
+	};
+
+	let repository = repository
+		.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	let Some(script) = script else {
+		crate::scripts::list_scripts(&repository);
+
+		return Ok(());
+	};
+
+	// Catch missing scripts and cycles before running anything
+	if crate::lua::script_path(&repository, &script).is_none() {
+		repository.script_order(&script)?;
+	}
+
+	let target = name.clone().unwrap_or_else(|| repository.config().name.to_string());
+	let message = format!("Running script{}\n", name.as_ref().map(|s| format!(" for link {}", s)).unwrap_or("".to_string()));
+
+	let options = RunOptions {
+		args: &args,
+		env: crate::scripts::script_env(&repository, workspace.as_ref()),
+		cwd: workspace.as_ref().zip(name.as_ref()).and_then(|(workspace, name)| workspace.link_cwd(name)),
+		..Default::default()
+	};
+
+	let run = |_: Option<&[String]>| {
+		info(&message);
 
-	let command = repository.script(&script)
-		.ok_or(UtilityError::ScriptNotFound(script))?;
+		crate::scripts::run_logged(&repository, &target, &script, &options)?;
 
-	info(&format!("Running script{}\n", name.map(|s| format!(" for link {}", s)).unwrap_or("".to_string())));
+		println!("");
+		success("Script completed successfully");
 
-	let status = std::process::Command::new("sh")
-		.current_dir(repository.path())
-		.arg("-c")
-		.arg(command)
-		.status()?;
+		Ok(())
+	};
 
+	if watch.watch {
+		let root = WatchRoot::repository(target.clone(), &repository);
 
-	if !status.success() {
-		return Err(UtilityError::ScriptError(format!("Exit code {}", status.code().unwrap_or(0))))
+		Watcher::new(vec![root], watch).run(run)
+	} else {
+		run(None)
 	}
+}
 
-	println!("");
-	success("Script completed successfully");
+fn cmd_exec_all(
+	filter: Option<String>,
+	jobs: Option<usize>,
+	affected: bool,
+	since: Option<String>,
+	script: String,
+	watch: WatchArgs,
+	args: Vec<String>
+) -> Result<(), UtilityError> {
+	let workspace = Workspace::locate_then_load(&current_dir()?)?
+		.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
 
-	Ok(())
+	let depends = workspace.link_dependencies();
+
+	let affected = affected
+		.then(|| affected_links(&workspace, &depends, since.as_deref()));
+
+	let mut links: Vec<String> = workspace.links()
+		.into_keys()
+		.filter(|link| filter.as_ref().map_or(true, |f| link.starts_with(f)))
+		.filter(|link| affected.as_ref().map_or(true, |a| a.contains(link)))
+		.collect();
+
+	links.sort();
+
+	let jobs = jobs.unwrap_or_else(crate::scripts::default_jobs);
+
+	let run = |changed: Option<&[String]>| {
+		// After a change, rerun the changed links and
+		// everything that depends on them
+		let links: Vec<String> = match changed {
+			Some(changed) => {
+				let rerun = with_dependents(changed.to_vec(), &depends);
+
+				links.iter().filter(|link| rerun.contains(*link)).cloned().collect()
+			},
+			None => links.clone()
+		};
+
+		info(&format!("Running script {} in {} links\n", script, links.len()));
+
+		let results = crate::scripts::run_in_links(&workspace, &links, &depends, &script, &args, jobs)?;
+
+		crate::scripts::summarize(&results)
+	};
+
+	if watch.watch {
+		let roots = links.iter()
+			.filter_map(|link| Some(WatchRoot::repository(link.clone(), &workspace.link(link)?)))
+			.collect();
+
+		Watcher::new(roots, watch).run(run)
+	} else {
+		run(None)
+	}
+}
+
+/// Get every link with changes since a git revision, or
+/// since its branch forked from its upstream if there is
+/// no revision, along with every link that depends on one
+/// of them. Links without a git checkout, or that can't be
+/// compared, are always considered changed.
+fn affected_links(workspace: &Workspace, depends: &HashMap<String, Vec<String>>, since: Option<&str>) -> HashSet<String> {
+	let changed: Vec<String> = workspace.links()
+		.into_keys()
+		.filter(|link| {
+			let checkout = workspace.link(link)
+				.and_then(|repository| repository.git_checkout());
+
+			let Some(checkout) = checkout else {
+				return true;
+			};
+
+			crate::git::changed_since(&checkout, since)
+				.unwrap_or_else(|err| {
+					info(&format!("Treating link {} as changed: {}", link, err.message()));
+
+					true
+				})
+		})
+		.collect();
+
+	with_dependents(changed, depends)
+}
+
+/// Get a set of links along with every link that depends
+/// on one of them
+fn with_dependents(mut links: Vec<String>, depends: &HashMap<String, Vec<String>>) -> HashSet<String> {
+	let mut found = HashSet::new();
+
+	while let Some(link) = links.pop() {
+		if !found.insert(link.clone()) {
+			continue;
+		}
+
+		links.extend(
+			depends.iter()
+				.filter(|(_, link_depends)| link_depends.contains(&link))
+				.map(|(dependent, _)| dependent.clone())
+		);
+	}
+
+	found
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn pick(repository: &str, taken: &[&str]) -> String {
+		free_name(repository, |name| !taken.contains(&name))
+	}
+
+	#[test]
+	fn uses_the_last_component() {
+		assert_eq!(pick("a/b/c", &[]), "c");
+	}
+
+	#[test]
+	fn qualifies_names_on_collision() {
+		assert_eq!(pick("a/b/c", &["c"]), "b-c");
+		assert_eq!(pick("a/b/c", &["c", "b-c"]), "a-b-c");
+	}
+
+	#[test]
+	fn numbers_names_once_qualified_names_collide() {
+		assert_eq!(pick("a/b/c", &["c", "b-c", "a-b-c"]), "c-2");
+		assert_eq!(pick("a/c", &["c", "a-c", "c-2"]), "c-3");
+	}
 }
@@ -1,10 +1,14 @@
-use batl::resource::{repository, Repository, Resource, Name};
+use batl::resource::{repository, Repository, Resource, Name, Workspace};
 use batl::resource::repository::CreateRepositoryOptions;
+use batl::resource::script::ScriptHost;
+use batl::resource::script::Hook;
 use batl::resource::tomlconfig::{TomlConfig, RepositoryGit0_2_2};
 use clap::Subcommand;
 use console::Term;
 use crate::output::*;
+use crate::scripts::RunOptions;
 use crate::utils::{UtilityError, BATL_NAME_REGEX};
+use crate::watch::{WatchArgs, WatchRoot, Watcher};
 use envfile::EnvFile;
 use git2::{FetchOptions, RemoteCallbacks, Progress};
 use git2::build::RepoBuilder;
@@ -39,18 +43,30 @@ pub enum Commands {
 		name: String
 	},
 	Publish {
+		#[arg(long = "registry")]
+		registry: Option<String>,
 		name: String
 	},
 	Fetch {
+		#[arg(long = "registry")]
+		registry: Option<String>,
 		name: String
 	},
 	Which {
 		name: String
 	},
+	Mv {
+		old: String,
+		new: String
+	},
 	Exec {
 		#[arg(short = 'n')]
 		name: Option<String>,
-		script: String
+		script: Option<String>,
+		#[command(flatten)]
+		watch: WatchArgs,
+		#[arg(last = true)]
+		args: Vec<String>
 	}
 }
 
@@ -77,17 +93,20 @@ pub fn run(cmd: Commands) -> Result<(), UtilityError> {
 		Commands::Archive { name } => {
 			cmd_archive(name)
 		},
-		Commands::Publish { name } => {
-			cmd_publish(name)
+		Commands::Publish { registry, name } => {
+			cmd_publish(registry, name)
 		},
-		Commands::Fetch { name } => {
-			cmd_fetch(name)
+		Commands::Fetch { registry, name } => {
+			cmd_fetch(registry, name)
 		},
 		Commands::Which { name } => {
 			cmd_which(name)
 		},
-		Commands::Exec { name, script } => {
-			cmd_exec(name, script)
+		Commands::Mv { old, new } => {
+			cmd_mv(old, new)
+		},
+		Commands::Exec { name, script, watch, args } => {
+			cmd_exec(name, script, watch, args)
 		}
 	}
 }
@@ -143,10 +162,12 @@ fn cmd_init(name: String) -> Result<(), UtilityError> {
 		return Err(UtilityError::InvalidName(name));
 	}
 
-	Repository::create(name.into(), Default::default())?;
+	let repository = Repository::create(name.into(), Default::default())?;
 
 	success("Initialized repository successfully");
 
+	crate::scripts::run_hook(&repository, Hook::PostInit)?;
+
 	Ok(())
 }
 
@@ -155,9 +176,12 @@ fn cmd_delete(name: String) -> Result<(), UtilityError> {
 		return Err(UtilityError::InvalidName(name));
 	}
 
-	Repository::load(name.into())?
-		.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?
-		.destroy()?;
+	let repository = Repository::load(name.into())?
+		.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	crate::scripts::run_hook(&repository, Hook::PreDelete)?;
+
+	repository.destroy()?;
 
 	success("Deleted repository successfully");
 
@@ -186,6 +210,13 @@ fn cmd_scaffold() -> Result<(), UtilityError> {
 	let repository = Repository::locate_then_load(&current_dir()?)?
 		.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
 
+	scaffold(&repository)
+}
+
+/// Clone a repository's git remote into its checkout and
+/// run its `post-scaffold` hook. Repositories without git
+/// are left alone.
+pub fn scaffold(repository: &Repository) -> Result<(), UtilityError> {
 	let config = repository.config();
 
 	if let Some(git) = config.git.clone() {
@@ -210,6 +241,8 @@ fn cmd_scaffold() -> Result<(), UtilityError> {
 		}
 
 		success("Successfully scaffolded repository");
+
+		crate::scripts::run_hook(repository, Hook::PostScaffold)?;
 	}
 
 	Ok(())
@@ -256,20 +289,36 @@ fn cmd_archive(name: String) -> Result<(), UtilityError> {
 	Ok(())
 }
 
-fn cmd_publish(name: String) -> Result<(), UtilityError> {
+fn registry_url(registry: &str) -> Result<String, UtilityError> {
 	let batlrc = batl::system::batlrc()
 		.ok_or(UtilityError::ResourceDoesNotExist("BatlRc".to_string()))?;
 
+	let registry = batlrc.registry(registry)
+		.ok_or(UtilityError::ResourceDoesNotExist(format!("Registry {}", registry)))?;
+
+	Ok(registry.url.trim_end_matches('/').to_string())
+}
+
+fn cmd_publish(registry: Option<String>, name: String) -> Result<(), UtilityError> {
+	let registry = crate::commands::auth::registry_name(registry)?;
+	let registry_url = registry_url(&registry)?;
+
+	let token = batl::system::registry_token(&registry)
+		.ok_or(UtilityError::NotAuthenticated(registry))?;
+
 	let repository = Repository::load(name.as_str().into())?
 		.ok_or(UtilityError::ResourceDoesNotExist("Repository".into()))?;
 
+	// The hook may rebuild the archive, so only open it after
+	crate::scripts::run_hook(&repository, Hook::PrePublish)?;
+
 	let archive = repository.archive()
 		.ok_or(UtilityError::ResourceDoesNotExist("Archive".into()))?;
 
-	let url = format!("https://api.batl.circetools.net/pkg/{}", &repository.name().to_string());
+	let url = format!("{}/pkg/{}", registry_url, &repository.name().to_string());
 
 	let resp = ureq::post(&url)
-		.set("x-api-key", &batlrc.api.credentials)
+		.set("x-api-key", &token)
 		.send(archive.to_file())?;
 
 	if resp.status() == 200 {
@@ -294,7 +343,7 @@ fn cmd_which(name: String) -> Result<(), UtilityError> {
 	Ok(())
 }
 
-fn cmd_exec(name: Option<String>, script: String) -> Result<(), UtilityError> {
+fn cmd_exec(name: Option<String>, script: Option<String>, watch: WatchArgs, args: Vec<String>) -> Result<(), UtilityError> {
 	let repository = match &name {
 		Some(val) => {
 			Repository::load(val.as_str().into())?
@@ -302,46 +351,131 @@ fn cmd_exec(name: Option<String>, script: String) -> Result<(), UtilityError> {
 		None => Repository::locate_then_load(&current_dir()?)?
 	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
 
-	let command = repository.script(&script)
-		.ok_or(UtilityError::ScriptNotFound(script))?;
+	let Some(script) = script else {
+		crate::scripts::list_scripts(&repository);
 
-	info(&format!("Running script{}\n", name.map(|s| format!(" for link {}", s)).unwrap_or("".to_string())));
+		return Ok(());
+	};
 
-	let status = std::process::Command::new("sh")
-		.current_dir(repository.path())
-		.arg("-c")
-		.arg(command)
-		.status()?;
+	// Catch missing scripts and cycles before running anything
+	if crate::lua::script_path(&repository, &script).is_none() {
+		repository.script_order(&script)?;
+	}
 
+	let target = name.clone().unwrap_or_else(|| repository.config().name.to_string());
+	let message = format!("Running script{}\n", name.map(|s| format!(" for link {}", s)).unwrap_or("".to_string()));
 
-	if !status.success() {
-		return Err(UtilityError::ScriptError(format!("Exit code {}", status.code().unwrap_or(0))))
-	}
+	let options = RunOptions {
+		args: &args,
+		env: crate::scripts::script_env(&repository, None),
+		..Default::default()
+	};
 
-	println!();
-	success("Script completed successfully");
+	let run = |_: Option<&[String]>| {
+		info(&message);
 
-	Ok(())
+		crate::scripts::run_logged(&repository, &target, &script, &options)?;
+
+		println!();
+		success("Script completed successfully");
+
+		Ok(())
+	};
+
+	if watch.watch {
+		let root = WatchRoot::repository(target.clone(), &repository);
+
+		Watcher::new(vec![root], watch).run(run)
+	} else {
+		run(None)
+	}
+}
+
+fn cmd_fetch(registry: Option<String>, name: String) -> Result<(), UtilityError> {
+	fetch(registry, &name)
 }
 
-fn cmd_fetch(name: String) -> Result<(), UtilityError> {
-	let url = format!("https://api.batl.circetools.net/pkg/{}", name);
+/// Download a repository from a registry and run its
+/// `post-fetch` hook
+pub fn fetch(registry: Option<String>, name: &str) -> Result<(), UtilityError> {
+	let registry = crate::commands::auth::registry_name(registry)?;
 
-	let resp = ureq::get(&url)
-		.call()?;
+	let url = format!("{}/pkg/{}", registry_url(&registry)?, name);
+
+	let mut request = ureq::get(&url);
+
+	if let Some(token) = batl::system::registry_token(&registry) {
+		request = request.set("x-api-key", &token);
+	}
+
+	let resp = request.call()?;
 
 	let body = resp.into_reader();
 	let mut tar = tar::Archive::new(body);
 
 	let repository_path = batl::system::repository_root()
 		.ok_or(UtilityError::ResourceDoesNotExist("Battalion setup".to_string()))?
-		.join(PathBuf::from(&Name::from(name.as_str())));
+		.join(PathBuf::from(&Name::from(name)));
 
 	std::fs::create_dir_all(&repository_path)?;
 
-	tar.unpack(repository_path)?;
+	tar.unpack(&repository_path)?;
 
 	success(&format!("Fetched repository {}", name));
 
+	let repository = Repository::load(name.into())?
+		.ok_or(UtilityError::ResourceDoesNotExist("Repository".into()))?;
+
+	crate::scripts::run_hook(&repository, Hook::PostFetch)?;
+
+	Ok(())
+}
+
+fn cmd_mv(old: String, new: String) -> Result<(), UtilityError> {
+	for name in [&old, &new] {
+		if !BATL_NAME_REGEX.is_match(name) {
+			return Err(UtilityError::InvalidName(name.clone()));
+		}
+	}
+
+	let mut repository = Repository::load(old.as_str().into())?
+		.ok_or(UtilityError::ResourceDoesNotExist(format!("Repository {}", old)))?;
+
+	if Repository::load(new.as_str().into()).ok().flatten().is_some() {
+		return Err(UtilityError::ResourceAlreadyExists(format!("Repository {}", new)));
+	}
+
+	let old: Name = old.as_str().into();
+
+	repository.rename(new.as_str().into())?;
+
+	success(&format!("Moved repository {} to {}", old, new));
+
+	let repository_root = batl::system::repository_root()
+		.ok_or(UtilityError::ResourceDoesNotExist("Repository root".to_string()))?;
+
+	for name in batl::resource::names_in(&repository_root)? {
+		let Some(mut dependent) = Repository::load(name.clone()).ok().flatten() else {
+			continue;
+		};
+
+		if dependent.rename_dependency(&old, repository.name())? {
+			info(&format!("Updated dependencies of repository {}", name));
+		}
+	}
+
+	let workspace_root = batl::system::workspace_root()
+		.ok_or(UtilityError::ResourceDoesNotExist("Workspace root".to_string()))?;
+
+	for name in batl::resource::names_in(&workspace_root)? {
+		let Some(mut workspace) = Workspace::load(name.clone()).ok().flatten() else {
+			continue;
+		};
+
+		if workspace.rename_repository(&old, &repository)? {
+			info(&format!("Updated links and dependencies of workspace {}", name));
+		}
+	}
+
 	Ok(())
 }
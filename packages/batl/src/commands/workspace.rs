@@ -1,8 +1,19 @@
-use batl::resource::{Resource, Name, Workspace};
+use batl::resource::{Repository, Resource, Name, Workspace};
+use batl::resource::link::Link;
+use batl::resource::repository::CreateRepositoryOptions;
+use batl::resource::tomlconfig::TomlConfig;
+use batl::resource::workspace::ManifestLatest;
+use batl::resource::script::ScriptHost;
 use clap::Subcommand;
+use console::Term;
 use crate::output::*;
-use crate::utils::{UtilityError, BATL_NAME_REGEX};
+use crate::scripts::RunOptions;
+use crate::utils::{UtilityError, BATL_LINK_REGEX, BATL_NAME_REGEX};
+use std::collections::VecDeque;
+use std::env::current_dir;
+use std::io::Write;
 use std::path::PathBuf;
+use std::sync::Mutex;
 
 
 #[derive(Subcommand)]
@@ -11,13 +22,61 @@ pub enum Commands {
 		filter: Option<String>
 	},
 	Init {
-		name: String
+		#[arg(required_unless_present = "reference")]
+		name: Option<String>,
+		#[arg(long = "ref")]
+		reference: Option<String>,
+		#[arg(long = "with-deps", requires = "reference")]
+		with_deps: bool
 	},
 	Delete {
 		name: String
 	},
 	Which {
 		name: String
+	},
+	Doctor {
+		#[arg(long = "fix")]
+		fix: bool,
+		name: Option<String>
+	},
+	Exec {
+		#[arg(short = 'n')]
+		name: Option<String>,
+		#[arg(long = "links", requires = "script")]
+		links: bool,
+		#[arg(short = 'j', long = "jobs")]
+		jobs: Option<usize>,
+		script: Option<String>,
+		#[arg(last = true)]
+		args: Vec<String>
+	},
+	Mv {
+		old: String,
+		new: String
+	},
+	Status {
+		name: Option<String>
+	},
+	Fetch {
+		#[arg(short = 'j', long = "jobs")]
+		jobs: Option<usize>,
+		name: Option<String>
+	},
+	Pull {
+		#[arg(short = 'j', long = "jobs")]
+		jobs: Option<usize>,
+		name: Option<String>
+	},
+	Export {
+		#[arg(short = 'o', long = "output")]
+		output: Option<PathBuf>,
+		name: String
+	},
+	Import {
+		#[arg(long = "registry")]
+		registry: Option<String>,
+		file: PathBuf
 	}
 }
 
@@ -26,14 +85,38 @@ pub fn run(cmd: Commands) -> Result<(), UtilityError> {
 		Commands::Ls { filter } => {
 			cmd_ls(filter)
 		},
-		Commands::Init { name } => {
-			cmd_init(name)
+		Commands::Init { name, reference, with_deps } => {
+			cmd_init(name, reference, with_deps)
 		},
 		Commands::Delete { name } => {
 			cmd_delete(name)
 		},
 		Commands::Which { name } => {
 			cmd_which(name)
+		},
+		Commands::Doctor { fix, name } => {
+			cmd_doctor(fix, name)
+		},
+		Commands::Exec { name, links, jobs, script, args } => {
+			cmd_exec(name, links, jobs, script, args)
+		},
+		Commands::Mv { old, new } => {
+			cmd_mv(old, new)
+		},
+		Commands::Status { name } => {
+			cmd_status(name)
+		},
+		Commands::Fetch { jobs, name } => {
+			cmd_update(name, jobs, false)
+		},
+		Commands::Pull { jobs, name } => {
+			cmd_update(name, jobs, true)
+		},
+		Commands::Export { output, name } => {
+			cmd_export(output, name)
+		},
+		Commands::Import { registry, file } => {
+			cmd_import(registry, file)
 		}
 	}
 }
@@ -84,17 +167,67 @@ fn cmd_ls(filter: Option<String>) -> Result<(), UtilityError> {
 	Ok(())
 }
 
-fn cmd_init(name: String) -> Result<(), UtilityError> {
+fn cmd_init(name: Option<String>, reference: Option<String>, with_deps: bool) -> Result<(), UtilityError> {
+	let repository = reference
+		.map(|reference| {
+			if !BATL_NAME_REGEX.is_match(&reference) {
+				return Err(UtilityError::InvalidName(reference));
+			}
+
+			Repository::load(reference.as_str().into())?
+				.ok_or(UtilityError::ResourceDoesNotExist(format!("Repository {}", reference)))
+		})
+		.transpose()?;
+
+	// Workspaces made from a repository share its name
+	let name = name
+		.or_else(|| repository.as_ref().map(|repo| repo.config().name.to_string()))
+		.unwrap_or_default();
+
 	if !BATL_NAME_REGEX.is_match(&name) {
 		return Err(UtilityError::InvalidName(name));
 	}
 
 	let name: Name = name.into();
 
-	Workspace::create(name.clone())?;
+	let mut workspace = Workspace::create(name.clone())?;
 
 	success(&format!("Workspace {} initialized", name.clone()));
 
+	let Some(repository) = repository else {
+		return Ok(());
+	};
+
+	let mut seen = vec![repository.config().name.clone()];
+	let mut to_link = VecDeque::from([repository]);
+
+	while let Some(repo) = to_link.pop_front() {
+		let link = crate::commands::link::default_name(&workspace, &repo.config().name.to_string());
+		workspace.create_link(&link, &repo)?;
+
+		success(&format!("Linked {} as {}", repo.config().name, link));
+
+		if !with_deps {
+			break;
+		}
+
+		let mut dependencies: Vec<Name> = repo.config().dependencies.keys().cloned().collect();
+		dependencies.sort_by_key(ToString::to_string);
+
+		for dependency in dependencies {
+			if seen.contains(&dependency) {
+				continue;
+			}
+
+			seen.push(dependency.clone());
+
+			match Repository::load(dependency.clone()) {
+				Ok(Some(dep_repo)) => to_link.push_back(dep_repo),
+				_ => error(&format!("Dependency {} is not installed, skipping", dependency))
+			}
+		}
+	}
+
 	Ok(())
 }
 
@@ -125,3 +258,468 @@ fn cmd_which(name: String) -> Result<(), UtilityError> {
 
 	Ok(())
 }
+
+fn cmd_doctor(fix: bool, name: Option<String>) -> Result<(), UtilityError> {
+	let mut workspace = match &name {
+		Some(val) => {
+			Workspace::load(val.as_str().into())?
+		},
+		None => Workspace::locate_then_load(&current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
+
+	let problems = workspace.diagnose()?;
+
+	if problems.is_empty() {
+		success("No problems found");
+
+		return Ok(());
+	}
+
+	if !fix {
+		for problem in &problems {
+			error(&problem.to_string());
+		}
+
+		println!();
+		info("Run with --fix to repair");
+
+		return Err(UtilityError::WorkspaceProblems(problems.len()));
+	}
+
+	let mut unfixed = 0;
+
+	for problem in &problems {
+		if workspace.repair(problem)? {
+			success(&format!("Fixed {}", problem));
+		} else {
+			unfixed += 1;
+			error(&format!("{}, fix manually", problem));
+		}
+	}
+
+	if unfixed > 0 {
+		return Err(UtilityError::WorkspaceProblems(unfixed));
+	}
+
+	Ok(())
+}
+
+fn cmd_exec(name: Option<String>, links: bool, jobs: Option<usize>, script: Option<String>, args: Vec<String>) -> Result<(), UtilityError> {
+	let workspace = match &name {
+		Some(val) => {
+			Workspace::load(val.as_str().into())?
+		},
+		None => Workspace::locate_then_load(&current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
+
+	let Some(script) = script else {
+		crate::scripts::list_scripts(&workspace);
+
+		return Ok(());
+	};
+
+	// Scripts the workspace doesn't define, or all scripts
+	// with --links, run in every link in dependency order
+	if links || workspace.script(&script).is_none() {
+		let mut names: Vec<String> = workspace.links().into_keys().collect();
+		names.sort();
+
+		let defined = names
+			.iter()
+			.filter_map(|link| workspace.link(link))
+			.any(|repository| crate::scripts::has_script(&repository, &script));
+
+		if !defined {
+			return Err(UtilityError::ScriptNotFound(script));
+		}
+
+		info(&format!("Running script {} in {} links\n", script, names.len()));
+
+		let depends = workspace.link_dependencies();
+		let jobs = jobs.unwrap_or_else(crate::scripts::default_jobs);
+		let results = crate::scripts::run_in_links(&workspace, &names, &depends, &script, &args, jobs)?;
+
+		return crate::scripts::summarize(&results);
+	}
+
+	// Catch cycles before running anything
+	workspace.script_order(&script)?;
+
+	info(&format!("Running script for workspace {}\n", workspace.config().name));
+
+	let options = RunOptions {
+		args: &args,
+		env: workspace.script_env(),
+		..Default::default()
+	};
+
+	let target = workspace.config().name.to_string();
+
+	crate::scripts::logged(&workspace, &target, &script, &options, |options| {
+		crate::scripts::run_host_script(&workspace, &script, options)
+	})?;
+
+	println!();
+	success("Script completed successfully");
+
+	Ok(())
+}
+
+fn cmd_mv(old: String, new: String) -> Result<(), UtilityError> {
+	for name in [&old, &new] {
+		if !BATL_NAME_REGEX.is_match(name) {
+			return Err(UtilityError::InvalidName(name.clone()));
+		}
+	}
+
+	let mut workspace = Workspace::load(old.as_str().into())?
+		.ok_or(UtilityError::ResourceDoesNotExist(format!("Workspace {}", old)))?;
+
+	if Workspace::load(new.as_str().into()).ok().flatten().is_some() {
+		return Err(UtilityError::ResourceAlreadyExists(format!("Workspace {}", new)));
+	}
+
+	workspace.rename(new.as_str().into())?;
+
+	success(&format!("Moved workspace {} to {}", old, new));
+
+	Ok(())
+}
+
+fn cmd_status(name: Option<String>) -> Result<(), UtilityError> {
+	let workspace = match &name {
+		Some(val) => {
+			Workspace::load(val.as_str().into())?
+		},
+		None => Workspace::locate_then_load(&current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
+
+	let mut links: Vec<String> = workspace.links().into_keys().collect();
+	links.sort();
+
+	let mut rows = vec![[
+		"LINK".to_string(),
+		"BRANCH".to_string(),
+		"UPSTREAM".to_string(),
+		"DIRTY".to_string(),
+		"STASHES".to_string()
+	]];
+
+	let mut dirty = 0;
+
+	for link in links {
+		let status = workspace.link(&link)
+			.ok_or_else(|| "repository could not be loaded".to_string())
+			.and_then(|repo| repo.git_checkout().ok_or_else(|| "no git checkout".to_string()))
+			.and_then(|checkout| crate::git::status(&checkout).map_err(|err| err.message().to_string()));
+
+		let row = match status {
+			Ok(status) => {
+				if status.is_dirty() {
+					dirty += 1;
+				}
+
+				let branch = status.branch.clone().unwrap_or_else(|| {
+					format!("(detached at {})", status.head.clone().unwrap_or_default())
+				});
+
+				let upstream = status.upstream
+					.map_or_else(|| "-".to_string(), |(ahead, behind)| format!("+{} -{}", ahead, behind));
+
+				[link, branch, upstream, status.dirty.to_string(), status.stashes.to_string()]
+			},
+			Err(reason) => [link, format!("({})", reason), "-".to_string(), "-".to_string(), "-".to_string()]
+		};
+
+		rows.push(row);
+	}
+
+	let mut widths = [0; 5];
+
+	for row in &rows {
+		for (width, cell) in widths.iter_mut().zip(row) {
+			*width = (*width).max(cell.len());
+		}
+	}
+
+	for row in &rows {
+		let line: Vec<String> = row.iter()
+			.zip(widths)
+			.map(|(cell, width)| format!("{:width$}", cell))
+			.collect();
+
+		println!("{}", line.join("  ").trim_end());
+	}
+
+	println!();
+
+	if dirty > 0 {
+		info(&format!("{} links have uncommitted changes or stashes", dirty));
+	} else {
+		success("Every checkout is clean");
+	}
+
+	Ok(())
+}
+
+/// Fetch every linked git checkout in parallel, fast-
+/// forwarding them when pulling. Links sharing a git
+/// repository, like worktrees, are fetched once.
+fn cmd_update(name: Option<String>, jobs: Option<usize>, pull: bool) -> Result<(), UtilityError> {
+	let workspace = match &name {
+		Some(val) => {
+			Workspace::load(val.as_str().into())?
+		},
+		None => Workspace::locate_then_load(&current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
+
+	let mut links: Vec<String> = workspace.links().into_keys().collect();
+	links.sort();
+
+	let mut groups: Vec<(PathBuf, Vec<(String, PathBuf)>)> = Vec::new();
+	let mut failed = 0;
+
+	for link in links {
+		let Some(checkout) = workspace.link(&link).and_then(|repo| repo.git_checkout()) else {
+			info(&format!("{}: no git checkout, skipping", link));
+			continue;
+		};
+
+		let common_dir = match crate::git::common_dir(&checkout) {
+			Ok(common_dir) => common_dir,
+			Err(err) => {
+				failed += 1;
+				error(&format!("{}: {}", link, err.message()));
+				continue;
+			}
+		};
+
+		match groups.iter_mut().find(|(dir, _)| *dir == common_dir) {
+			Some((_, group)) => group.push((link, checkout)),
+			None => groups.push((common_dir, vec![(link, checkout)]))
+		}
+	}
+
+	let queue = Mutex::new((0..groups.len()).collect::<VecDeque<usize>>());
+	let progress = Mutex::new(vec![(0, 0); groups.len()]);
+	let results = Mutex::new(Vec::new());
+
+	std::thread::scope(|scope| {
+		for _ in 0..jobs.unwrap_or_else(crate::scripts::default_jobs).max(1) {
+			scope.spawn(|| {
+				loop {
+					let Some(index) = queue.lock().unwrap().pop_front() else {
+						break;
+					};
+
+					let group = &groups[index].1;
+
+					let fetched = crate::git::fetch(&group[0].1, |stats| fetch_progress(&progress, index, &stats));
+
+					for (link, checkout) in group {
+						let outcome = fetched
+							.as_ref()
+							.map_err(|err| err.message().to_string())
+							.and_then(|_| crate::git::update(checkout, pull).map_err(|err| err.message().to_string()));
+
+						results.lock().unwrap().push((link.clone(), outcome));
+					}
+				}
+			});
+		}
+	});
+
+	println!();
+
+	let mut results = results.into_inner().unwrap();
+	results.sort_by(|a, b| a.0.cmp(&b.0));
+
+	let mut attention = 0;
+
+	for (link, outcome) in results {
+		match outcome {
+			Ok(update) if update.needs_attention() => {
+				attention += 1;
+				error(&format!("{}: {}", link, update));
+			},
+			Ok(update) => success(&format!("{}: {}", link, update)),
+			Err(reason) => {
+				failed += 1;
+				error(&format!("{}: {}", link, reason));
+			}
+		}
+	}
+
+	if attention > 0 {
+		println!();
+		info(&format!("{} links were left alone and need attention", attention));
+	}
+
+	if failed > 0 {
+		return Err(UtilityError::GitUpdateFailed(failed));
+	}
+
+	Ok(())
+}
+
+/// Show the combined progress of every fetch
+fn fetch_progress(progress: &Mutex<Vec<(usize, usize)>>, index: usize, stats: &git2::Progress<'_>) -> bool {
+	let mut progress = progress.lock().unwrap();
+	progress[index] = (stats.received_objects(), stats.total_objects());
+
+	let (received, total) = progress.iter()
+		.fold((0, 0), |(received, total), (r, t)| (received + r, total + t));
+
+	let percentage = if total == 0 { 0.0 } else { received as f64 / total as f64 };
+
+	let mut term = Term::stdout();
+
+	term.clear_line().unwrap();
+	term.write_fmt(format_args!("Fetching repositories... {:.2}%", percentage * 100.0)).unwrap();
+	term.flush().unwrap();
+
+	true
+}
+
+fn cmd_export(output: Option<PathBuf>, name: String) -> Result<(), UtilityError> {
+	if !BATL_NAME_REGEX.is_match(&name) {
+		return Err(UtilityError::InvalidName(name));
+	}
+
+	let workspace = Workspace::load(name.as_str().into())?
+		.ok_or(UtilityError::ResourceDoesNotExist("Workspace".into()))?;
+
+	let manifest = toml::to_string(&workspace.manifest())
+		.map_err(|_| UtilityError::InvalidConfig)?;
+
+	match output {
+		Some(output) => {
+			std::fs::write(&output, manifest)?;
+
+			success(&format!("Exported workspace {} to {}", name, output.display()));
+		},
+		None => print!("{}", manifest)
+	}
+
+	Ok(())
+}
+
+/// Check every name in a manifest before anything is
+/// written, since manifests are shared between people
+fn validate_manifest(manifest: &ManifestLatest) -> Result<(), UtilityError> {
+	let check_name = |name: &Name| {
+		let name = name.to_string();
+
+		if BATL_NAME_REGEX.is_match(&name) {
+			Ok(())
+		} else {
+			Err(UtilityError::InvalidName(name))
+		}
+	};
+
+	check_name(&manifest.config.workspace.name)?;
+
+	for (name, entry) in manifest.repositories.iter().flatten() {
+		check_name(name)?;
+
+		if let Some(git) = &entry.git {
+			let plain = std::path::Path::new(&git.path)
+				.components()
+				.all(|component| matches!(component, std::path::Component::Normal(_)));
+
+			if !plain {
+				return Err(UtilityError::InvalidPath(git.path.clone()));
+			}
+		}
+	}
+
+	for (link_name, link) in manifest.config.links.iter().flatten() {
+		if !BATL_LINK_REGEX.is_match(link_name) {
+			return Err(UtilityError::InvalidName(link_name.clone()));
+		}
+
+		check_name(&Link::from(link.clone()).repository)?;
+	}
+
+	Ok(())
+}
+
+fn cmd_import(registry: Option<String>, file: PathBuf) -> Result<(), UtilityError> {
+	let manifest = ManifestLatest::read_toml(&file)?;
+
+	validate_manifest(&manifest)?;
+
+	let name = manifest.config.workspace.name.clone();
+
+	if Workspace::load(name.clone()).ok().flatten().is_some() {
+		return Err(UtilityError::ResourceAlreadyExists(format!("Workspace {}", name)));
+	}
+
+	let mut repositories: Vec<_> = manifest.repositories.clone().unwrap_or_default().into_iter().collect();
+	repositories.sort_by_key(|(name, _)| name.to_string());
+
+	let mut failed = 0;
+
+	for (repo_name, entry) in repositories {
+		if let Some(repository) = Repository::load(repo_name.clone()).ok().flatten() {
+			match &entry.version {
+				Some(version) if *version != repository.config().version => {
+					info(&format!("Repository {} is {}, manifest has {}", repo_name, repository.config().version, version));
+				},
+				_ => {}
+			}
+
+			continue;
+		}
+
+		info(&format!("Collecting repository {}", repo_name));
+
+		let result = match entry.git {
+			Some(git) => Repository::create(repo_name.clone(), CreateRepositoryOptions::git(git))
+				.map_err(UtilityError::from)
+				.and_then(|repository| crate::commands::repository::scaffold(&repository)),
+			None => crate::commands::repository::fetch(registry.clone(), &repo_name.to_string())
+		};
+
+		if let Err(err) = result {
+			failed += 1;
+			error(&format!("{}: {}", repo_name, err));
+		}
+	}
+
+	// Only create the workspace once everything it links
+	// is in place, so a failed import can just be run again
+	if failed > 0 {
+		return Err(UtilityError::ResourceNotCollected(format!("{} repositories, run the import again to retry", failed)));
+	}
+
+	let links = manifest.config.links.clone().unwrap_or_default();
+	let mut workspace = Workspace::create_from(manifest.config)?;
+
+	success(&format!("Workspace {} initialized", name));
+
+	let mut links: Vec<(String, Link)> = links.into_iter().map(|(name, link)| (name, link.into())).collect();
+	links.sort_by(|a, b| a.0.cmp(&b.0));
+
+	for (link_name, link) in links {
+		let Some(repository) = Repository::load(link.repository.clone()).ok().flatten() else {
+			failed += 1;
+			error(&format!("Repository {} is missing, skipping link {}", link.repository, link_name));
+			continue;
+		};
+
+		match workspace.create_link_with(&link_name, &repository, link.mode, link.path) {
+			Ok(()) => success(&format!("Linked {} as {}", link.repository, link_name)),
+			Err(err) => {
+				failed += 1;
+				error(&format!("{}: {}", link_name, UtilityError::from(err)));
+			}
+		}
+	}
+
+	if failed > 0 {
+		return Err(UtilityError::ResourceNotCollected(format!("{} links", failed)));
+	}
+
+	Ok(())
+}
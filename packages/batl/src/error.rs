@@ -20,7 +20,15 @@ pub enum CreateResourceError {
 	#[error("Battalion not set up")]
 	NotSetup,
 	#[error("Resource already exists")]
-	AlreadyExists
+	AlreadyExists,
+	#[error("Repository has no git checkout")]
+	NoGitCheckout,
+	#[error("Repository has no archive")]
+	NoArchive,
+	#[error("Invalid path: {0}")]
+	InvalidPath(String),
+	#[error("Git error: {0}")]
+	GitError(#[from] git2::Error)
 }
 
 #[derive(Debug, Error)]
@@ -64,5 +72,27 @@ pub enum DeleteResourceError {
 	#[error("IO Error: {0}")]
 	IoError(#[from] std::io::Error),
 	#[error("Resource does not exist")]
-	DoesNotExist
+	DoesNotExist,
+	#[error("Worktree has uncommitted changes")]
+	UncommittedChanges,
+	#[error("Git error: {0}")]
+	GitError(#[from] git2::Error)
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ScriptProcessError {
+	#[error("Invalid quoting in command: {0}")]
+	InvalidQuoting(String),
+	#[error("Invalid path: {0}")]
+	InvalidCwd(String)
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ScriptGraphError {
+	#[error("Script not found: {0}")]
+	NotFound(String),
+	#[error("Script dependency cycle: {}", .0.join(" -> "))]
+	Cycle(Vec<String>)
 }
@@ -0,0 +1,273 @@
+use std::path::{Path, PathBuf};
+
+
+/// Check whether anything in a git checkout changed since
+/// the given revision. This includes commits since the
+/// revision, staged and unstaged changes, and untracked
+/// files. Without a revision, changes are counted from
+/// where the checked out branch forked from its upstream,
+/// and checkouts without an upstream return an error.
+pub fn changed_since(checkout: &Path, since: Option<&str>) -> Result<bool, git2::Error> {
+	let repo = git2::Repository::open(checkout)?;
+
+	let base = match since {
+		Some(since) => repo.revparse_single(since)?,
+		None => {
+			let head = repo.head()?.peel_to_commit()?.id();
+			let upstream = repo.revparse_single("@{upstream}")?.peel_to_commit()?.id();
+
+			repo.find_object(repo.merge_base(head, upstream)?, None)?
+		}
+	};
+
+	let tree = base.peel_to_tree()?;
+
+	let mut options = git2::DiffOptions::new();
+	options
+		.include_untracked(true)
+		.recurse_untracked_dirs(true);
+
+	let diff = repo.diff_tree_to_workdir_with_index(Some(&tree), Some(&mut options))?;
+
+	Ok(diff.deltas().len() > 0)
+}
+
+/// Find the git folder a checkout shares with all of its
+/// worktrees
+pub fn common_dir(checkout: &Path) -> Result<PathBuf, git2::Error> {
+	let repo = git2::Repository::open(checkout)?;
+	let path = repo.path();
+
+	// Worktrees keep their own folder under `worktrees`
+	// in the main git folder
+	let common = if repo.is_worktree() {
+		path.parent().and_then(Path::parent).unwrap_or(path)
+	} else {
+		path
+	};
+
+	Ok(common.to_path_buf())
+}
+
+/// The state of a git checkout
+pub struct Status {
+	/// The checked out branch, or `None` with a detached
+	/// `HEAD`
+	pub branch: Option<String>,
+
+	/// The short id of the commit at `HEAD`, if there is one
+	pub head: Option<String>,
+
+	/// Commits ahead of and behind the upstream branch, if
+	/// the branch has one
+	pub upstream: Option<(usize, usize)>,
+
+	/// Files with staged, unstaged or untracked changes
+	pub dirty: usize,
+
+	/// Entries in the stash
+	pub stashes: usize
+}
+
+impl Status {
+	/// Whether anything in the checkout isn't committed
+	pub fn is_dirty(&self) -> bool {
+		self.dirty > 0 || self.stashes > 0
+	}
+}
+
+/// Read the state of a git checkout
+pub fn status(checkout: &Path) -> Result<Status, git2::Error> {
+	let mut repo = git2::Repository::open(checkout)?;
+
+	let (branch, head_id) = match repo.head() {
+		Ok(head) => {
+			let branch = head.is_branch()
+				.then(|| head.shorthand().map(ToOwned::to_owned))
+				.flatten();
+
+			(branch, head.target())
+		},
+		// Unborn branches have no commit to point at yet
+		Err(_) => {
+			let branch = repo.find_reference("HEAD")
+				.ok()
+				.and_then(|head| head.symbolic_target().map(|target| target.trim_start_matches("refs/heads/").to_owned()));
+
+			(branch, None)
+		}
+	};
+
+	let upstream = match (&branch, head_id) {
+		(Some(branch), Some(local)) => repo.find_branch(branch, git2::BranchType::Local)
+			.and_then(|branch| branch.upstream())
+			.ok()
+			.and_then(|upstream| upstream.get().target())
+			.map(|remote| repo.graph_ahead_behind(local, remote))
+			.transpose()?,
+		_ => None
+	};
+
+	let mut options = git2::StatusOptions::new();
+	options
+		.include_untracked(true)
+		.recurse_untracked_dirs(true)
+		.include_ignored(false);
+
+	let dirty = repo.statuses(Some(&mut options))?.len();
+
+	let mut stashes = 0;
+	repo.stash_foreach(|_, _, _| {
+		stashes += 1;
+		true
+	})?;
+
+	Ok(Status {
+		branch,
+		head: head_id.map(|id| id.to_string().chars().take(7).collect()),
+		upstream,
+		dirty,
+		stashes
+	})
+}
+
+/// What updating a git checkout from its upstream did
+pub enum Update {
+	/// Nothing new upstream
+	UpToDate,
+
+	/// Commits only exist locally
+	Ahead(usize),
+
+	/// Upstream has new commits that were not applied
+	Behind(usize),
+
+	/// The checkout was fast-forwarded by some commits
+	FastForwarded(usize),
+
+	/// Both sides have commits, given as ahead and behind
+	Diverged(usize, usize),
+
+	/// Tracked files have local changes, so it was not
+	/// fast-forwarded
+	Dirty,
+
+	/// `HEAD` isn't on a branch
+	Detached,
+
+	/// The branch doesn't track an upstream branch
+	NoUpstream
+}
+
+impl Update {
+	/// Whether the checkout needs someone to look at it
+	pub fn needs_attention(&self) -> bool {
+		matches!(self, Self::Diverged(..) | Self::Dirty | Self::Detached)
+	}
+}
+
+impl std::fmt::Display for Update {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::UpToDate => write!(f, "up to date"),
+			Self::Ahead(ahead) => write!(f, "{} commits ahead", ahead),
+			Self::Behind(behind) => write!(f, "{} commits behind", behind),
+			Self::FastForwarded(behind) => write!(f, "fast-forwarded {} commits", behind),
+			Self::Diverged(ahead, behind) => write!(f, "diverged, {} ahead and {} behind", ahead, behind),
+			Self::Dirty => write!(f, "local changes, not updated"),
+			Self::Detached => write!(f, "detached HEAD, not updated"),
+			Self::NoUpstream => write!(f, "no upstream branch")
+		}
+	}
+}
+
+/// Fetch the remote the checked out branch tracks, or
+/// `origin` if it doesn't track one. Returns `false` if
+/// there is no remote to fetch.
+pub fn fetch<F>(checkout: &Path, progress: F) -> Result<bool, git2::Error>
+where
+	F: FnMut(git2::Progress<'_>) -> bool
+{
+	let repo = git2::Repository::open(checkout)?;
+
+	let remote_name = repo.head()
+		.ok()
+		.filter(git2::Reference::is_branch)
+		.and_then(|head| head.name().map(ToOwned::to_owned))
+		.and_then(|head| repo.branch_upstream_remote(&head).ok())
+		.and_then(|remote| remote.as_str().map(ToOwned::to_owned))
+		.unwrap_or_else(|| "origin".to_string());
+
+	let Ok(mut remote) = repo.find_remote(&remote_name) else {
+		return Ok(false);
+	};
+
+	let mut callbacks = git2::RemoteCallbacks::new();
+	callbacks.transfer_progress(progress);
+
+	let mut options = git2::FetchOptions::new();
+	options.remote_callbacks(callbacks);
+
+	remote.fetch(&[] as &[&str], Some(&mut options), None)?;
+
+	Ok(true)
+}
+
+/// Compare a checkout with its upstream branch, fast-
+/// forwarding it if asked and nothing is in the way.
+/// Checkouts that diverged or have local changes to
+/// tracked files are never touched, and local changes
+/// are reported whether or not it was asked to update.
+pub fn update(checkout: &Path, fast_forward: bool) -> Result<Update, git2::Error> {
+	let repo = git2::Repository::open(checkout)?;
+
+	if repo.head_detached()? {
+		return Ok(Update::Detached);
+	}
+
+	let mut head = repo.head()?;
+
+	let Some(branch) = head.shorthand().map(ToOwned::to_owned) else {
+		return Ok(Update::NoUpstream);
+	};
+
+	let Ok(upstream) = repo.find_branch(&branch, git2::BranchType::Local).and_then(|branch| branch.upstream()) else {
+		return Ok(Update::NoUpstream);
+	};
+
+	let (Some(local), Some(remote)) = (head.target(), upstream.get().target()) else {
+		return Ok(Update::NoUpstream);
+	};
+
+	let (ahead, behind) = repo.graph_ahead_behind(local, remote)?;
+
+	if let (ahead @ 1.., behind @ 1..) = (ahead, behind) {
+		return Ok(Update::Diverged(ahead, behind));
+	}
+
+	let mut options = git2::StatusOptions::new();
+	options
+		.include_untracked(false)
+		.include_ignored(false);
+
+	if !repo.statuses(Some(&mut options))?.is_empty() {
+		return Ok(Update::Dirty);
+	}
+
+	match (ahead, behind) {
+		(0, 0) => return Ok(Update::UpToDate),
+		(ahead, 0) => return Ok(Update::Ahead(ahead)),
+		(_, behind) if !fast_forward => return Ok(Update::Behind(behind)),
+		_ => {}
+	}
+
+	let target = repo.find_object(remote, None)?;
+
+	let mut checkout_options = git2::build::CheckoutBuilder::new();
+	checkout_options.safe();
+
+	repo.checkout_tree(&target, Some(&mut checkout_options))?;
+	head.set_target(remote, &format!("batl: fast-forward {} to {}", branch, remote))?;
+
+	Ok(Update::FastForwarded(behind))
+}
@@ -0,0 +1,200 @@
+use batl::resource::{Repository, Resource, Workspace};
+use batl::resource::script::ScriptHost;
+use crate::scripts::{output_line, run_process, run_script, RunOptions};
+use crate::utils::UtilityError;
+use mlua::{Lua, Table, Value, Variadic};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+
+/// Get the Lua file to run for a script name, if there is
+/// one. Named scripts always win over files.
+pub fn script_path(repository: &Repository, name: &str) -> Option<PathBuf> {
+	if repository.script(name).is_some() {
+		return None;
+	}
+
+	let path = repository.path().join(name);
+
+	(path.extension().is_some_and(|ext| ext == "lua") && path.is_file()).then_some(path)
+}
+
+/// Run a Lua file in an embedded runtime. The file gets a
+/// `batl` table with:
+///
+/// - `repository`: the `name`, `version` and `path` of the
+///   repository
+/// - `args`: arguments passed after `--`
+/// - `env`: the script environment
+/// - `links`: every link in the workspace, if run in one,
+///   with their `repository` and `path`
+/// - `config`: the repository's `batl.toml`
+/// - `exec(program, args, opts)`: run a program, raising
+///   an error if it fails. `opts` may set `cwd` and `env`.
+/// - `run(script, args)`: run another repository script
+///
+/// Printed output follows the same prefixing and logging
+/// as any other script.
+pub fn run(repository: &Repository, path: &Path, options: &RunOptions) -> Result<(), UtilityError> {
+	let source = std::fs::read_to_string(path)?;
+	let chunk_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+	let lua = Lua::new();
+
+	let result = lua.scope(|scope| {
+		let batl = lua.create_table()?;
+
+		batl.set("repository", repository_table(&lua, repository)?)?;
+		batl.set("args", options.args.to_vec())?;
+		batl.set("env", options.env.clone())?;
+		batl.set("links", links_table(&lua, options)?)?;
+		batl.set("config", config_table(&lua, repository)?)?;
+
+		batl.set("exec", scope.create_function(|_, (program, args, opts): (String, Option<Vec<String>>, Option<Table>)| {
+			let mut process = Command::new(&program);
+
+			let root = options.cwd.as_deref().unwrap_or(repository.path());
+
+			let cwd = match opts.as_ref().map(|opts| opts.get::<_, Option<String>>("cwd")).transpose()?.flatten() {
+				Some(cwd) => root.join(cwd),
+				None => root.to_path_buf()
+			};
+
+			let env: HashMap<String, String> = opts.as_ref()
+				.map(|opts| opts.get::<_, Option<HashMap<String, String>>>("env"))
+				.transpose()?
+				.flatten()
+				.unwrap_or_default();
+
+			process
+				.args(args.unwrap_or_default())
+				.current_dir(cwd)
+				.envs(&options.env)
+				.envs(env);
+
+			let failure = run_process(&mut process, options.prefix.as_deref(), options.log, None)
+				.map_err(mlua::Error::external)?;
+
+			match failure {
+				Some(failure) => Err(mlua::Error::RuntimeError(format!("{}: {}", program, failure))),
+				None => Ok(())
+			}
+		})?)?;
+
+		batl.set("run", scope.create_function(|_, (script, args): (String, Option<Vec<String>>)| {
+			let args = args.unwrap_or_default();
+
+			let script_options = RunOptions {
+				args: &args,
+				env: options.env.clone(),
+				prefix: options.prefix.clone(),
+				log: options.log,
+				cwd: options.cwd.clone()
+			};
+
+			run_script(repository, &script, &script_options)
+				.map_err(mlua::Error::external)
+		})?)?;
+
+		let globals = lua.globals();
+		let tostring: mlua::Function = globals.get("tostring")?;
+
+		// Route print through the run's output so it gets
+		// prefixed and logged
+		globals.set("print", scope.create_function(move |_, values: Variadic<Value>| {
+			let mut parts = Vec::new();
+
+			for value in values {
+				parts.push(tostring.call::<_, String>(value)?);
+			}
+
+			output_line(options.prefix.as_deref(), options.log, &parts.join("\t"), false);
+
+			Ok(())
+		})?)?;
+
+		globals.set("batl", batl)?;
+
+		lua.load(&source)
+			.set_name(format!("@{}", chunk_name))
+			.exec()
+	});
+
+	result.map_err(|err| UtilityError::ScriptError(format!("{}: {}", chunk_name, err)))
+}
+
+/// Describe the repository for Lua
+fn repository_table<'lua>(lua: &'lua Lua, repository: &Repository) -> mlua::Result<Table<'lua>> {
+	let table = lua.create_table()?;
+
+	table.set("name", repository.config().name.to_string())?;
+	table.set("version", repository.config().version.to_string())?;
+	table.set("path", repository.path().to_string_lossy().into_owned())?;
+
+	Ok(table)
+}
+
+/// Describe every link in the workspace the script runs
+/// in, if any
+fn links_table<'lua>(lua: &'lua Lua, options: &RunOptions) -> mlua::Result<Table<'lua>> {
+	let table = lua.create_table()?;
+
+	let workspace = options.env.get("BATL_WORKSPACE")
+		.and_then(|path| Workspace::from_path(Path::new(path)).ok());
+
+	let Some(workspace) = workspace else {
+		return Ok(table);
+	};
+
+	for (link, name) in workspace.links() {
+		let link_table = lua.create_table()?;
+
+		link_table.set("repository", name.to_string())?;
+		link_table.set("path", workspace.path().join(&link).to_string_lossy().into_owned())?;
+
+		table.set(link, link_table)?;
+	}
+
+	Ok(table)
+}
+
+/// Read the repository's `batl.toml` into a Lua table
+fn config_table<'lua>(lua: &'lua Lua, repository: &Repository) -> mlua::Result<Value<'lua>> {
+	let config = std::fs::read_to_string(repository.path().join("batl.toml"))
+		.map_err(mlua::Error::external)?;
+
+	let config: toml::Value = toml::from_str(&config)
+		.map_err(mlua::Error::external)?;
+
+	toml_to_lua(lua, &config)
+}
+
+/// Convert a toml value into the matching Lua value
+fn toml_to_lua<'lua>(lua: &'lua Lua, value: &toml::Value) -> mlua::Result<Value<'lua>> {
+	Ok(match value {
+		toml::Value::String(string) => Value::String(lua.create_string(string)?),
+		toml::Value::Integer(integer) => Value::Integer(*integer),
+		toml::Value::Float(float) => Value::Number(*float),
+		toml::Value::Boolean(boolean) => Value::Boolean(*boolean),
+		toml::Value::Datetime(datetime) => Value::String(lua.create_string(datetime.to_string())?),
+		toml::Value::Array(array) => {
+			let table = lua.create_table()?;
+
+			for (index, item) in array.iter().enumerate() {
+				table.raw_set(index + 1, toml_to_lua(lua, item)?)?;
+			}
+
+			Value::Table(table)
+		},
+		toml::Value::Table(map) => {
+			let table = lua.create_table()?;
+
+			for (key, item) in map {
+				table.raw_set(key.as_str(), toml_to_lua(lua, item)?)?;
+			}
+
+			Value::Table(table)
+		}
+	})
+}
@@ -1,8 +1,12 @@
 use clap::{Parser, Subcommand, Args};
 
 mod commands;
+mod git;
+mod lua;
 mod output;
+mod scripts;
 mod utils;
+mod watch;
 
 #[derive(Parser)]
 #[command(name = "batl")]
@@ -27,7 +31,15 @@ enum SubCommand {
 		name: String
 	},
 	Upgrade,
-	Auth
+	Auth {
+		#[command(subcommand)]
+		subcmd: Option<commands::auth::Commands>
+	},
+	Logs {
+		#[arg(long = "last")]
+		last: bool,
+		name: Option<String>
+	}
 }
 
 #[derive(Args)]
@@ -48,7 +60,8 @@ fn main() {
 		SubCommand::Add { name } => commands::cmd_add(name),
 		SubCommand::Remove { name } => commands::cmd_remove(name),
 		SubCommand::Upgrade => commands::cmd_upgrade(),
-		SubCommand::Auth => commands::cmd_auth()
+		SubCommand::Auth { subcmd } => commands::auth::run(subcmd.unwrap_or_default()),
+		SubCommand::Logs { last, name } => commands::cmd_logs(last, name)
 	};
 
 	if let Err(err) = result {
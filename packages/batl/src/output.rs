@@ -12,3 +12,7 @@ pub fn error(message: &str) {
 pub fn info(message: &str) {
 	println!("[{}] {}", "INFO".blue(), message)
 }
+
+pub fn warning(message: &str) {
+	println!("[{}] {}", "WARN".yellow(), message)
+}
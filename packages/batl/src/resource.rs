@@ -7,8 +7,12 @@ use std::path::{Path, PathBuf};
 
 pub mod archive;
 pub mod batlrc;
+pub mod cache;
+pub mod history;
+pub mod link;
 pub mod repository;
 pub mod restrict;
+pub mod script;
 pub mod tomlconfig;
 pub mod workspace;
 
@@ -160,6 +164,87 @@ impl Serialize for Name {
 	}
 }
 
+/// Find the name of every resource under a root, following
+/// the `@namespace` folder layout
+/// 
+/// # Errors
+/// 
+/// Returns any IO errors that are received in the process
+#[inline]
+pub fn names_in(root: &Path) -> Result<Vec<Name>, std::io::Error> {
+	let mut to_search = vec![(Vec::new(), root.to_path_buf())];
+	let mut found = Vec::new();
+
+	while let Some((parts, path)) = to_search.pop() {
+		for entry in std::fs::read_dir(path)? {
+			let entry = entry?;
+
+			if !entry.file_type()?.is_dir() {
+				continue;
+			}
+
+			let filename = entry.file_name().to_string_lossy().into_owned();
+			let mut entry_parts: Vec<String> = parts.clone();
+
+			if let Some(namespace) = filename.strip_prefix('@') {
+				entry_parts.push(namespace.to_owned());
+				to_search.push((entry_parts, entry.path()));
+			} else if !parts.is_empty() {
+				entry_parts.push(filename);
+				found.push(Name::new(entry_parts));
+			}
+		}
+	}
+
+	found.sort_by_key(ToString::to_string);
+
+	Ok(found)
+}
+
+/// Move a resource folder, then clean up any namespace
+/// folders left empty under the root
+/// 
+/// # Errors
+/// 
+/// Returns any IO errors that are received in the process
+pub(crate) fn move_resource(from: &Path, to: &Path, root: &Path) -> Result<(), std::io::Error> {
+	if let Some(parent) = to.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+
+	std::fs::rename(from, to)?;
+
+	let mut parent = from.parent();
+
+	while let Some(dir) = parent {
+		if dir == root || std::fs::remove_dir(dir).is_err() {
+			break;
+		}
+
+		parent = dir.parent();
+	}
+
+	Ok(())
+}
+
+/// Creates a symlink to a file or directory, OS independent
+/// 
+/// # Errors
+/// 
+/// Returns any IO errors that are received in the process
+#[inline]
+pub fn symlink_any(original: &Path, link: &Path) -> Result<(), std::io::Error> {
+	#[cfg(unix)]
+	return std::os::unix::fs::symlink(original, link);
+
+	#[cfg(target_os = "windows")]
+	return if original.is_dir() {
+		std::os::windows::fs::symlink_dir(original, link)
+	} else {
+		std::os::windows::fs::symlink_file(original, link)
+	};
+}
+
 /// Creates a symlink directory, OS independent
 /// 
 /// # Errors
@@ -1,9 +1,108 @@
+use crate::error::ReadConfigError;
 use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::path::Path;
+use super::tomlconfig::TomlConfig;
 
 
 pub type BatlRcLatest = BatlRc0_2_2;
-pub type BatlRc0_2_2 = BatlRc0_2_1;
+pub type CredentialsLatest = Credentials0_2_2;
 
+/// The API key written by older versions before any
+/// key was provided
+pub const PLACEHOLDER_CREDENTIALS: &str = "YOUR-KEY-GOES-HERE";
+
+/// The name of the registry used when none is configured
+pub const DEFAULT_REGISTRY: &str = "batl";
+
+/// The URL of the default registry
+pub const DEFAULT_REGISTRY_URL: &str = "https://api.batl.circetools.net";
+
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[allow(clippy::exhaustive_structs)]
+pub struct BatlRc0_2_2 {
+	pub api: Api0_2_2,
+	pub registries: Option<HashMap<String, Registry0_2_2>>
+}
+
+impl BatlRc0_2_2 {
+	/// Get a registry by name. The default registry is
+	/// always available, even if it is not configured.
+	#[inline]
+	#[must_use]
+	pub fn registry(&self, name: &str) -> Option<Registry0_2_2> {
+		let configured = self.registries
+			.as_ref()
+			.and_then(|registries| registries.get(name))
+			.cloned();
+
+		if configured.is_none() && name == DEFAULT_REGISTRY {
+			return Some(Registry0_2_2::default());
+		}
+
+		configured
+	}
+}
+
+impl Default for BatlRc0_2_2 {
+	#[inline]
+	fn default() -> Self {
+		let mut registries = HashMap::new();
+		registries.insert(DEFAULT_REGISTRY.to_owned(), Registry0_2_2::default());
+
+		Self {
+			api: Api0_2_2::default(),
+			registries: Some(registries)
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[allow(clippy::exhaustive_structs)]
+pub struct Api0_2_2 {
+	/// The registry used when none is specified
+	pub registry: String
+}
+
+impl Default for Api0_2_2 {
+	#[inline]
+	fn default() -> Self {
+		Self {
+			registry: DEFAULT_REGISTRY.to_owned()
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[allow(clippy::exhaustive_structs)]
+pub struct Registry0_2_2 {
+	pub url: String
+}
+
+impl Default for Registry0_2_2 {
+	#[inline]
+	fn default() -> Self {
+		Self {
+			url: DEFAULT_REGISTRY_URL.to_owned()
+		}
+	}
+}
+
+/// Registry credentials, kept separate from the batlrc
+/// so they can be stored with restricted permissions
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[allow(clippy::exhaustive_structs)]
+pub struct Credentials0_2_2 {
+	pub tokens: HashMap<String, String>
+}
+
+impl From<BatlRc0_2_1> for BatlRc0_2_2 {
+	#[inline]
+	fn from(_value: BatlRc0_2_1) -> Self {
+		Self::default()
+	}
+}
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
 #[allow(clippy::exhaustive_structs)]
@@ -21,7 +120,52 @@ impl Default for Api0_2_1 {
 	#[inline]
 	fn default() -> Self {
 		Self {
-			credentials: "YOUR-KEY-GOES-HERE".to_owned()
+			credentials: PLACEHOLDER_CREDENTIALS.to_owned()
+		}
+	}
+}
+
+#[non_exhaustive]
+pub enum AnyBatlRc {
+	V0_2_2(BatlRc0_2_2),
+	V0_2_1(BatlRc0_2_1)
+}
+
+impl AnyBatlRc {
+	/// Get the credentials stored in a legacy batlrc, if
+	/// they were ever set
+	#[inline]
+	#[must_use]
+	pub fn legacy_credentials(&self) -> Option<String> {
+		match self {
+			Self::V0_2_1(v021) if v021.api.credentials != PLACEHOLDER_CREDENTIALS => {
+				Some(v021.api.credentials.clone())
+			},
+			_ => None
+		}
+	}
+}
+
+#[allow(clippy::missing_trait_methods)]
+impl TomlConfig for AnyBatlRc {
+	#[inline]
+	fn read_toml(path: &Path) -> Result<Self, ReadConfigError> {
+		let config_str = std::fs::read_to_string(path)?;
+
+		if let Ok(v022) = toml::from_str(&config_str) {
+			return Ok(Self::V0_2_2(v022));
+		}
+
+		Ok(Self::V0_2_1(toml::from_str(&config_str)?))
+	}
+}
+
+impl From<AnyBatlRc> for BatlRcLatest {
+	#[inline]
+	fn from(value: AnyBatlRc) -> Self {
+		match value {
+			AnyBatlRc::V0_2_1(v021) => v021.into(),
+			AnyBatlRc::V0_2_2(v022) => v022
 		}
 	}
 }
@@ -0,0 +1,196 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use super::script::{Script, ScriptHost};
+
+
+/// The record of the last successful run of a script
+/// with declared inputs, stored under the gen folder
+pub struct ScriptCache {
+	/// The cache folder for this script
+	path: PathBuf
+}
+
+impl ScriptCache {
+	/// Get the cache for a script in a repository or
+	/// workspace. Returns `None` if battalion is not set up.
+	#[inline]
+	#[must_use]
+	pub fn new<H: ScriptHost>(host: &H, script: &str) -> Option<Self> {
+		let path = crate::system::gen_root()?
+			.join("cache")
+			.join(host.cache_key())
+			.join(script);
+
+		Some(Self { path })
+	}
+
+	/// Hash everything that affects a script run: the
+	/// command, its arguments, the environment it runs
+	/// with, and the contents of every input file. `env`
+	/// is the environment the script's own is layered
+	/// over. Inputs are globs relative to the resource,
+	/// including hidden files, and respect `batl.ignore`
+	/// and `.gitignore`.
+	///
+	/// # Errors
+	///
+	/// Propogates any errors from reading the inputs
+	#[inline]
+	pub fn input_hash<H: ScriptHost, S: ::std::hash::BuildHasher>(
+		host: &H,
+		script: &Script,
+		args: &[String],
+		env: &HashMap<String, String, S>
+	) -> Result<String, std::io::Error> {
+		let root = host.path();
+		let mut hasher = Sha256::new();
+
+		hasher.update(script.command.clone().unwrap_or_default());
+		hasher.update([0]);
+
+		for arg in args {
+			hasher.update(arg);
+			hasher.update([0]);
+		}
+
+		let mut env: Vec<_> = env
+			.iter()
+			.filter(|(key, _)| !script.env.contains_key(*key))
+			.chain(&script.env)
+			.collect();
+
+		env.sort();
+
+		for (key, value) in env {
+			hasher.update(format!("{key}={value}"));
+			hasher.update([0]);
+		}
+
+		let mut overrides = ignore::overrides::OverrideBuilder::new(root);
+
+		for glob in &script.inputs {
+			overrides.add(glob).map_err(std::io::Error::other)?;
+		}
+
+		// Overrides beat ignore files while walking, so match
+		// the input globs afterwards instead
+		let inputs = overrides.build().map_err(std::io::Error::other)?;
+
+		let mut walk_builder = ignore::WalkBuilder::new(root);
+		walk_builder
+			.hidden(false)
+			.add_custom_ignore_filename("batl.ignore")
+			.filter_entry(|entry| entry.file_name() != ".git");
+
+		let mut files = Vec::new();
+
+		for result in walk_builder.build() {
+			let entry = result.map_err(std::io::Error::other)?;
+
+			let is_file = entry.file_type().is_some_and(|t| t.is_file());
+
+			if is_file && inputs.matched(entry.path(), false).is_whitelist() {
+				files.push(entry.into_path());
+			}
+		}
+
+		files.sort();
+
+		let mut buffer = Vec::new();
+
+		for file in files {
+			let rel_path = pathdiff::diff_paths(&file, root).unwrap_or_else(|| file.clone());
+
+			buffer.clear();
+			std::fs::File::open(&file)?.read_to_end(&mut buffer)?;
+
+			hasher.update(rel_path.to_string_lossy().as_bytes());
+			hasher.update([0]);
+			hasher.update(&buffer);
+		}
+
+		Ok(format!("{:x}", hasher.finalize()))
+	}
+
+	/// Check whether the last successful run had the
+	/// same input hash
+	#[inline]
+	#[must_use]
+	pub fn is_fresh(&self, hash: &str) -> bool {
+		std::fs::read_to_string(self.path.join("hash"))
+			.is_ok_and(|stored| stored.trim() == hash)
+	}
+
+	/// Copy any outputs that are missing from the
+	/// resource back out of the cache
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors
+	#[inline]
+	pub fn restore_outputs<H: ScriptHost>(&self, host: &H, script: &Script) -> Result<Vec<String>, std::io::Error> {
+		let mut restored = Vec::new();
+
+		for output in &script.outputs {
+			let target = host.path().join(output);
+			let cached = self.path.join("outputs").join(output);
+
+			if !target.exists() && cached.exists() {
+				copy_recursive(&cached, &target)?;
+				restored.push(output.clone());
+			}
+		}
+
+		Ok(restored)
+	}
+
+	/// Record a successful run, saving a copy of every
+	/// output
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors
+	#[inline]
+	pub fn store<H: ScriptHost>(&self, hash: &str, host: &H, script: &Script) -> Result<(), std::io::Error> {
+		let outputs_path = self.path.join("outputs");
+
+		if outputs_path.exists() {
+			std::fs::remove_dir_all(&outputs_path)?;
+		}
+
+		std::fs::create_dir_all(&outputs_path)?;
+
+		for output in &script.outputs {
+			let source = host.path().join(output);
+
+			if source.exists() {
+				copy_recursive(&source, &outputs_path.join(output))?;
+			}
+		}
+
+		std::fs::write(self.path.join("hash"), hash)
+	}
+}
+
+/// Copy a file or directory, creating parent folders
+fn copy_recursive(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+	if let Some(parent) = to.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+
+	if from.is_dir() {
+		std::fs::create_dir_all(to)?;
+
+		for entry in std::fs::read_dir(from)? {
+			let entry = entry?;
+
+			copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+		}
+
+		Ok(())
+	} else {
+		std::fs::copy(from, to).map(|_| ())
+	}
+}
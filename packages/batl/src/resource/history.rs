@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use super::tomlconfig::{write_toml, TomlConfig};
+
+
+/// How many runs are kept under the gen folder. Older
+/// runs are removed as new ones start.
+pub const MAX_RUNS: usize = 200;
+
+/// What happened during a single script or command run
+#[derive(Serialize, Deserialize, Clone)]
+#[non_exhaustive]
+pub struct RunRecord {
+	/// The repository or link the run happened in
+	pub target: String,
+
+	/// The script that was run, `None` for plain commands
+	pub script: Option<String>,
+
+	/// The command line that was run
+	pub command: Option<String>,
+
+	/// When the run started, in seconds since the unix
+	/// epoch
+	pub started: u64,
+
+	/// How long the run took in milliseconds
+	pub duration: u64,
+
+	/// Whether the run succeeded
+	pub success: bool,
+
+	/// A description of how the run ended
+	pub status: String
+}
+
+/// A run being recorded under the gen folder. Every run
+/// gets a metadata file and a file with its output.
+pub struct RunLog {
+	/// The path of the log, without an extension
+	path: PathBuf,
+	record: RunRecord,
+	start: Instant,
+	output: Mutex<File>
+}
+
+impl RunLog {
+	/// Start recording a run. Returns `None` if battalion
+	/// is not set up.
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors from creating the log
+	#[inline]
+	pub fn create(target: String, script: Option<String>, command: Option<String>) -> Result<Option<Self>, std::io::Error> {
+		let Some(root) = logs_root() else {
+			return Ok(None);
+		};
+
+		std::fs::create_dir_all(&root)?;
+		prune(&root, MAX_RUNS - 1);
+
+		let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+		// Sorts by start time, the random suffix keeps runs
+		// that start together apart
+		let id = format!("{}-{:04x}", now.as_millis(), rand::random::<u16>());
+		let path = root.join(id);
+
+		let output = File::create(path.with_extension("log"))?;
+
+		Ok(Some(Self {
+			path,
+			record: RunRecord {
+				target,
+				script,
+				command,
+				started: now.as_secs(),
+				duration: 0,
+				success: false,
+				status: "Running".to_owned()
+			},
+			start: Instant::now(),
+			output: Mutex::new(output)
+		}))
+	}
+
+	/// Record a line of output. Failures to write are
+	/// ignored, logging should never stop a run.
+	#[inline]
+	pub fn line(&self, line: &str) {
+		if let Ok(mut output) = self.output.lock() {
+			let _ = writeln!(output, "{line}");
+		}
+	}
+
+	/// Finish the run, saving its metadata
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors from writing the metadata
+	#[inline]
+	pub fn finish(mut self, success: bool, status: String) -> Result<(), std::io::Error> {
+		self.record.duration = u64::try_from(self.start.elapsed().as_millis()).unwrap_or(u64::MAX);
+		self.record.success = success;
+		self.record.status = status;
+
+		write_toml(&self.path.with_extension("toml"), &self.record)
+	}
+}
+
+/// A finished run stored under the gen folder
+pub struct LoggedRun {
+	pub id: String,
+	pub record: RunRecord,
+
+	/// The file with everything the run printed
+	pub output: PathBuf
+}
+
+/// List every finished run, oldest first
+///
+/// # Errors
+///
+/// Propogates any IO errors from reading the logs folder
+#[inline]
+pub fn runs() -> Result<Vec<LoggedRun>, std::io::Error> {
+	let Some(root) = logs_root() else {
+		return Ok(Vec::new());
+	};
+
+	if !root.exists() {
+		return Ok(Vec::new());
+	}
+
+	let mut runs = Vec::new();
+
+	for entry in std::fs::read_dir(root)? {
+		let path = entry?.path();
+
+		if path.extension().is_some_and(|ext| ext == "toml") {
+			let Some(id) = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()) else {
+				continue;
+			};
+
+			let Ok(record) = RunRecord::read_toml(&path) else {
+				continue;
+			};
+
+			runs.push(LoggedRun {
+				id,
+				record,
+				output: path.with_extension("log")
+			});
+		}
+	}
+
+	runs.sort_by(|a, b| a.id.cmp(&b.id));
+
+	Ok(runs)
+}
+
+/// Remove the oldest runs until at most `keep` are left.
+/// Failures are ignored, since a leftover log does no
+/// harm.
+fn prune(root: &Path, keep: usize) {
+	let Ok(entries) = std::fs::read_dir(root) else {
+		return;
+	};
+
+	let mut ids: Vec<String> = entries
+		.filter_map(|entry| Some(entry.ok()?.path().file_stem()?.to_string_lossy().into_owned()))
+		.collect();
+
+	ids.sort();
+	ids.dedup();
+
+	let excess = ids.len().saturating_sub(keep);
+
+	for id in ids.iter().take(excess) {
+		for extension in ["toml", "log"] {
+			let _ = std::fs::remove_file(root.join(id).with_extension(extension));
+		}
+	}
+}
+
+/// The folder run logs are stored in
+fn logs_root() -> Option<PathBuf> {
+	crate::system::gen_root().map(|p| p.join("logs"))
+}
@@ -0,0 +1,292 @@
+use std::path::{Component, Path, PathBuf};
+use super::{tomlconfig, Name};
+
+
+/// A workspace link to a repository
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct Link {
+	/// The linked repository
+	pub repository: Name,
+
+	/// How the repository appears in the workspace
+	pub mode: LinkMode,
+
+	/// The folder inside the repository the link points
+	/// at, if not the repository itself
+	pub path: Option<PathBuf>
+}
+
+impl Link {
+	/// Create a link that symlinks to the repository
+	#[inline]
+	#[must_use]
+	pub fn symlink(repository: Name) -> Self {
+		Self {
+			repository,
+			mode: LinkMode::Symlink,
+			path: None
+		}
+	}
+
+	/// The folder the link points at in a repository
+	#[inline]
+	#[must_use]
+	pub fn target(&self, repository: &Path) -> PathBuf {
+		self.path
+			.as_ref()
+			.map_or_else(|| repository.to_path_buf(), |path| repository.join(path))
+	}
+}
+
+/// How a linked repository appears in a workspace
+#[derive(Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LinkMode {
+	/// A symlink to the repository, shared with every
+	/// other workspace that links it
+	#[default]
+	Symlink,
+
+	/// A folder mirroring the repository, with a git
+	/// worktree of its checkout on a branch of its own
+	Worktree {
+		branch: String
+	},
+
+	/// A copy of the repository, refreshed by syncing
+	Copy,
+
+	/// A folder tree of hardlinks to the repository's
+	/// files
+	HardlinkTree,
+
+	/// A read-only snapshot of the repository's archive
+	Readonly
+}
+
+impl LinkMode {
+	/// Whether the link holds its own files that need
+	/// syncing to pick up changes in the repository
+	#[inline]
+	#[must_use]
+	pub const fn is_materialized(&self) -> bool {
+		matches!(self, Self::Copy | Self::HardlinkTree | Self::Readonly)
+	}
+}
+
+impl From<tomlconfig::Link0_2_2> for Link {
+	#[inline]
+	fn from(value: tomlconfig::Link0_2_2) -> Self {
+		match value {
+			tomlconfig::Link0_2_2::Repository(repository) => Self::symlink(repository),
+			tomlconfig::Link0_2_2::Table(table) => {
+				let mode = match table.mode {
+					Some(tomlconfig::LinkMode0_2_2::Worktree) => LinkMode::Worktree {
+						branch: table.branch.unwrap_or_default()
+					},
+					Some(tomlconfig::LinkMode0_2_2::Copy) => LinkMode::Copy,
+					Some(tomlconfig::LinkMode0_2_2::HardlinkTree) => LinkMode::HardlinkTree,
+					Some(tomlconfig::LinkMode0_2_2::Readonly) => LinkMode::Readonly,
+					Some(tomlconfig::LinkMode0_2_2::Symlink) | None => LinkMode::Symlink
+				};
+
+				Self {
+					repository: table.repository,
+					mode,
+					path: table.path.map(PathBuf::from)
+				}
+			}
+		}
+	}
+}
+
+impl From<Link> for tomlconfig::Link0_2_2 {
+	#[inline]
+	fn from(value: Link) -> Self {
+		let (mode, branch) = match value.mode {
+			LinkMode::Symlink if value.path.is_none() => return Self::Repository(value.repository),
+			LinkMode::Symlink => (tomlconfig::LinkMode0_2_2::Symlink, None),
+			LinkMode::Worktree { branch } => (tomlconfig::LinkMode0_2_2::Worktree, Some(branch)),
+			LinkMode::Copy => (tomlconfig::LinkMode0_2_2::Copy, None),
+			LinkMode::HardlinkTree => (tomlconfig::LinkMode0_2_2::HardlinkTree, None),
+			LinkMode::Readonly => (tomlconfig::LinkMode0_2_2::Readonly, None)
+		};
+
+		Self::Table(tomlconfig::LinkTable0_2_2 {
+			repository: value.repository,
+			mode: Some(mode),
+			branch,
+			path: value.path.map(|path| path.to_string_lossy().replace('\\', "/"))
+		})
+	}
+}
+
+/// Check that a link path, or a script's `cwd`, is a
+/// folder that stays inside the repository. Absolute
+/// paths and `..` are never allowed, and symlinks can't
+/// lead outside either.
+#[inline]
+#[must_use]
+pub fn is_valid_path(repository: &Path, path: &Path) -> bool {
+	let plain = path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+
+	if !plain {
+		return false;
+	}
+
+	let (Ok(root), Ok(target)) = (repository.canonicalize(), repository.join(path).canonicalize()) else {
+		return false;
+	};
+
+	target.starts_with(root) && target.is_dir()
+}
+
+/// Mirror a repository's files into a folder, either by
+/// copying or hardlinking them. Anything in `.gitignore`
+/// or `batl.ignore` is left out, along with `.git`.
+/// Symlinks are recreated as they are.
+/// 
+/// # Errors
+/// 
+/// Propogates any IO errors
+pub(crate) fn materialize(from: &Path, to: &Path, hardlink: bool) -> Result<(), std::io::Error> {
+	let mut walk_builder = ignore::WalkBuilder::new(from);
+	walk_builder
+		.hidden(false)
+		.require_git(false)
+		.add_custom_ignore_filename("batl.ignore")
+		.filter_entry(|entry| entry.file_name() != ".git");
+
+	std::fs::create_dir_all(to)?;
+
+	for result in walk_builder.build() {
+		let entry = result.map_err(std::io::Error::other)?;
+
+		let Ok(rel_path) = entry.path().strip_prefix(from) else {
+			continue;
+		};
+
+		if rel_path.as_os_str().is_empty() {
+			continue;
+		}
+
+		let target = to.join(rel_path);
+		let Some(file_type) = entry.file_type() else {
+			continue;
+		};
+
+		if file_type.is_dir() {
+			std::fs::create_dir_all(&target)?;
+		} else if file_type.is_symlink() {
+			super::symlink_any(&std::fs::read_link(entry.path())?, &target)?;
+		} else if hardlink {
+			std::fs::hard_link(entry.path(), &target)?;
+		} else {
+			std::fs::copy(entry.path(), &target)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Set a folder and everything under it as read-only,
+/// or back to writable. Folders are made writable before
+/// their entries and read-only after them, so the tree
+/// can always be walked and changed along the way.
+/// 
+/// # Errors
+/// 
+/// Propogates any IO errors
+pub(crate) fn set_readonly(path: &Path, readonly: bool) -> Result<(), std::io::Error> {
+	if !readonly {
+		set_entry_readonly(path, false)?;
+	}
+
+	for entry in std::fs::read_dir(path)? {
+		let entry = entry?;
+		let file_type = entry.file_type()?;
+
+		if file_type.is_dir() {
+			set_readonly(&entry.path(), readonly)?;
+		} else if file_type.is_file() {
+			set_entry_readonly(&entry.path(), readonly)?;
+		}
+	}
+
+	if readonly {
+		set_entry_readonly(path, true)?;
+	}
+
+	Ok(())
+}
+
+/// Set a single file or folder as read-only, or back to
+/// writable by its owner
+fn set_entry_readonly(path: &Path, readonly: bool) -> Result<(), std::io::Error> {
+	let mut permissions = std::fs::metadata(path)?.permissions();
+
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+
+		let mode = permissions.mode();
+		permissions.set_mode(if readonly { mode & !0o222 } else { mode | 0o200 });
+	}
+
+	#[cfg(not(unix))]
+	#[allow(clippy::permissions_set_readonly_false)]
+	permissions.set_readonly(readonly);
+
+	std::fs::set_permissions(path, permissions)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A fresh repository folder with a `src` folder and a
+	/// sibling folder outside it
+	fn fixture(name: &str) -> (PathBuf, PathBuf) {
+		let root = std::env::temp_dir().join(format!("batl-link-{}-{}", name, std::process::id()));
+		let _ = std::fs::remove_dir_all(&root);
+
+		let repository = root.join("repository");
+		let outside = root.join("outside");
+
+		std::fs::create_dir_all(repository.join("src")).unwrap();
+		std::fs::create_dir_all(&outside).unwrap();
+
+		(repository, outside)
+	}
+
+	#[test]
+	fn accepts_folders_inside() {
+		let (repository, _) = fixture("inside");
+
+		assert!(is_valid_path(&repository, Path::new("src")));
+		assert!(is_valid_path(&repository, Path::new("./src")));
+		assert!(!is_valid_path(&repository, Path::new("missing")));
+	}
+
+	#[test]
+	fn rejects_parent_and_absolute_paths() {
+		let (repository, outside) = fixture("escape");
+
+		assert!(!is_valid_path(&repository, Path::new("../outside")));
+		assert!(!is_valid_path(&repository, Path::new("src/../../outside")));
+		assert!(!is_valid_path(&repository, &outside));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn rejects_symlinks_leading_outside() {
+		let (repository, outside) = fixture("symlink");
+
+		std::os::unix::fs::symlink(&outside, repository.join("out")).unwrap();
+		std::os::unix::fs::symlink(repository.join("src"), repository.join("in")).unwrap();
+
+		assert!(!is_valid_path(&repository, Path::new("out")));
+		assert!(is_valid_path(&repository, Path::new("in")));
+	}
+}
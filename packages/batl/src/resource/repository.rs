@@ -5,6 +5,7 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use super::{tomlconfig, Name, Resource};
 use super::archive::Archive;
+use super::script::{Hook, Script, ScriptHost};
 use super::restrict::{Condition, Settings as RestrictSettings};
 use super::tomlconfig::TomlConfig;
 
@@ -81,7 +82,7 @@ impl Repository {
 		std::fs::create_dir_all(&repo_path)?;
 
 		let mut scripts = HashMap::new();
-		scripts.insert("build".to_owned(), "echo \"No build targets\" && exit 1".to_owned());
+		scripts.insert("build".to_owned(), tomlconfig::Script0_2_2::Command("echo \"No build targets\" && exit 1".to_owned()));
 
 		let mut restrictions = HashMap::new();
 
@@ -105,7 +106,8 @@ impl Repository {
 			},
 			scripts: Some(scripts),
 			dependencies: None,
-			restrict: Some(restrictions)
+			restrict: Some(restrictions),
+			hooks: None
 		};
 
 		tomlconfig::write_toml(&repo_path.join("batl.toml"), &toml)?;
@@ -150,6 +152,21 @@ impl Repository {
 		})
 	}
 
+	/// Loads a repository that lives somewhere other
+	/// than its own folder, such as a copy in a
+	/// workspace, keeping its real name.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found along the way
+	#[inline]
+	pub fn from_path_named(name: Name, path: &Path) -> Result<Self, batlerror::GeneralResourceError> {
+		Ok(Self {
+			name,
+			..Self::from_path(path)?
+		})
+	}
+
 	/// Searches the path - along with all of its
 	/// parents - for a working configuration.
 	/// 
@@ -165,18 +182,44 @@ impl Repository {
 			.transpose()
 	}
 
-	/// Get the scripts hashmap
+	/// Get the scripts that run for a lifecycle hook, in
+	/// the order they are declared
 	#[inline]
 	#[must_use]
-	pub fn scripts(&self) -> HashMap<String, String> {
-		self.config.scripts.clone()
+	pub fn hook_scripts(&self, hook: Hook) -> Vec<String> {
+		self.config.hooks.get(&hook).cloned().unwrap_or_default()
+	}
+
+	/// Get the path of the repository's git checkout, if
+	/// it has a git remote configured
+	#[inline]
+	#[must_use]
+	pub fn git_checkout(&self) -> Option<PathBuf> {
+		self.config.git
+			.as_ref()
+			.map(|git| self.path.join(&git.path))
 	}
 
-	/// Get a specific script
+	/// Get the environment variables scripts in this
+	/// repository run with. This is everything in the
+	/// `batl.env` file, along with battalion context
+	/// variables.
 	#[inline]
 	#[must_use]
-	pub fn script(&self, name: &str) -> Option<String> {
-		self.scripts().get(name).cloned()
+	pub fn script_env(&self) -> HashMap<String, String> {
+		let mut env: HashMap<String, String> = envfile::EnvFile::new(self.path.join("batl.env"))
+			.map(|file| file.store.into_iter().collect())
+			.unwrap_or_default();
+
+		if let Some(batl_root) = crate::system::batl_root() {
+			env.insert("BATL_ROOT".to_owned(), batl_root.to_string_lossy().to_string());
+		}
+
+		env.insert("BATL_REPOSITORY_NAME".to_owned(), self.config.name.to_string());
+		env.insert("BATL_REPOSITORY_VERSION".to_owned(), self.config.version.to_string());
+		env.insert("BATL_REPOSITORY_PATH".to_owned(), self.path.to_string_lossy().to_string());
+
+		env
 	}
 
 	/// Destroy the repository from the filesystem, this
@@ -191,6 +234,73 @@ impl Repository {
 		Ok(())
 	}
 
+	/// Move the repository to a new name. This moves its
+	/// folder and archive, and updates the name in its
+	/// config. Links and dependencies elsewhere that use
+	/// the old name are left to the caller.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found along the way, including
+	/// if a repository with the new name exists
+	#[inline]
+	pub fn rename(&mut self, name: Name) -> Result<(), batlerror::CreateResourceError> {
+		let root = crate::system::repository_root()
+			.ok_or(batlerror::CreateResourceError::NotSetup)?;
+
+		let path = root.join(PathBuf::from(&name));
+
+		if path.exists() {
+			return Err(batlerror::CreateResourceError::AlreadyExists);
+		}
+
+		super::move_resource(&self.path, &path, &root)?;
+
+		if let Some(archives) = crate::system::archive_root().map(|p| p.join("repositories")) {
+			let archive = archives.join(format!("{}.tar", self.name));
+
+			if archive.exists() {
+				let new_archive = archives.join(format!("{name}.tar"));
+
+				if let Some(parent) = new_archive.parent() {
+					std::fs::create_dir_all(parent)?;
+				}
+
+				std::fs::rename(archive, new_archive)?;
+			}
+		}
+
+		self.path = path;
+		self.name = name.clone();
+		self.config.name = name;
+
+		self.save()?;
+
+		Ok(())
+	}
+
+	/// Point dependencies on a renamed repository at its
+	/// new name, including restricted dependencies.
+	/// Returns whether anything changed.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors from saving
+	#[inline]
+	pub fn rename_dependency(&mut self, old: &Name, new: &Name) -> Result<bool, std::io::Error> {
+		let mut changed = rename_key(&mut self.config.dependencies, old, new);
+
+		for settings in self.config.restrict.values_mut() {
+			changed |= rename_key(&mut settings.dependencies, old, new);
+		}
+
+		if changed {
+			self.save()?;
+		}
+
+		Ok(changed)
+	}
+
 	/// Creates an archive, this is deprecated
 	/// 
 	/// # Errors
@@ -254,6 +364,18 @@ impl Repository {
 	}
 }
 
+/// Move a map entry to a new key, returning whether it
+/// was there
+pub(crate) fn rename_key<V>(map: &mut HashMap<Name, V>, old: &Name, new: &Name) -> bool {
+	match map.remove(old) {
+		Some(value) => {
+			map.insert(new.clone(), value);
+			true
+		},
+		None => false
+	}
+}
+
 impl Resource for Repository {
 	type Config = Config;
 
@@ -273,15 +395,28 @@ impl Resource for Repository {
 	}
 }
 
+impl ScriptHost for Repository {
+	#[inline]
+	fn scripts(&self) -> HashMap<String, Script> {
+		self.config.scripts.clone()
+	}
+
+	#[inline]
+	fn cache_key(&self) -> PathBuf {
+		PathBuf::from(&self.config.name)
+	}
+}
+
 #[derive(Clone)]
 #[non_exhaustive]
 pub struct Config {
 	pub name: Name,
 	pub version: Version,
 	pub git: Option<GitConfig>,
-	pub scripts: HashMap<String, String>,
+	pub scripts: HashMap<String, Script>,
 	pub dependencies: HashMap<Name, String>,
-	pub restrict: HashMap<Condition, RestrictSettings>
+	pub restrict: HashMap<Condition, RestrictSettings>,
+	pub hooks: HashMap<Hook, Vec<String>>
 }
 
 #[derive(Clone)]
@@ -337,7 +472,8 @@ pub struct TomlConfig0_2_2 {
 	pub repository: tomlconfig::Repository0_2_2,
 	pub scripts: Option<tomlconfig::Scripts0_2_2>,
 	pub dependencies: Option<tomlconfig::Dependencies0_2_2>,
-	pub restrict: Option<tomlconfig::Restrict0_2_2>
+	pub restrict: Option<tomlconfig::Restrict0_2_2>,
+	pub hooks: Option<tomlconfig::Hooks0_2_2>
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
@@ -359,9 +495,10 @@ impl From<TomlConfig0_2_1> for TomlConfigLatest {
 				version: value.repository.version,
 				git: value.repository.git
 			},
-			scripts: value.scripts,
+			scripts: value.scripts.map(tomlconfig::upgrade_scripts_0_2_1),
 			dependencies: value.dependencies,
-			restrict: None
+			restrict: None,
+			hooks: None
 		}
 	}
 }
@@ -385,9 +522,10 @@ impl From<TomlConfig0_2_0> for TomlConfigLatest {
 				version: value.repository.version,
 				git: value.repository.git
 			},
-			scripts: value.scripts,
+			scripts: value.scripts.map(tomlconfig::upgrade_scripts_0_2_1),
 			dependencies: value.dependencies,
-			restrict: None
+			restrict: None,
+			hooks: None
 		}
 	}
 }
@@ -406,13 +544,24 @@ impl From<TomlConfig0_2_2> for Config {
 			.map(|(k, v)| (k.into(), v.into()))
 			.collect::<HashMap<_, _>>();
 
+		let hooks = value.hooks
+			.unwrap_or_default()
+			.into_iter()
+			.map(|(k, v)| (k.into(), v))
+			.collect::<HashMap<_, _>>();
+
 		Self {
 			name: value.repository.name,
 			version: value.repository.version,
 			git,
-			scripts: value.scripts.unwrap_or_default(),
+			scripts: value.scripts
+				.unwrap_or_default()
+				.into_iter()
+				.map(|(k, v)| (k, v.into()))
+				.collect(),
 			dependencies: value.dependencies.unwrap_or_default(),
-			restrict
+			restrict,
+			hooks
 		}
 	}
 }
@@ -429,6 +578,10 @@ impl From<Config> for TomlConfigLatest {
 			.map(|(k, v)| (k.into(), v.into()))
 			.collect::<HashMap<_, _>>();
 
+		let hooks = value.hooks.into_iter()
+			.map(|(k, v)| (k.into(), v))
+			.collect::<HashMap<_, _>>();
+
 		Self {
 			environment: tomlconfig::EnvironmentLatest::default(),
 			repository: tomlconfig::RepositoryLatest {
@@ -436,9 +589,12 @@ impl From<Config> for TomlConfigLatest {
 				version: value.version,
 				git
 			},
-			scripts: tomlconfig::hashmap_to_option_hashmap(value.scripts),
+			scripts: tomlconfig::hashmap_to_option_hashmap(
+				value.scripts.into_iter().map(|(k, v)| (k, v.into())).collect()
+			),
 			dependencies: tomlconfig::hashmap_to_option_hashmap(value.dependencies),
-			restrict: tomlconfig::hashmap_to_option_hashmap(restrict)
+			restrict: tomlconfig::hashmap_to_option_hashmap(restrict),
+			hooks: tomlconfig::hashmap_to_option_hashmap(hooks)
 		}
 	}
 }
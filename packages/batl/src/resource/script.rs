@@ -0,0 +1,648 @@
+use core::fmt::{Display, Formatter};
+use crate::error::{ScriptGraphError, ScriptProcessError};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use super::{tomlconfig, Resource};
+
+
+/// A script that can be run in a repository or workspace
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub struct Script {
+	/// The command to run, scripts without one only run
+	/// their dependencies
+	pub command: Option<String>,
+
+	/// Scripts that must run before this one
+	pub depends: Vec<String>,
+
+	/// The directory to run in, relative to the resource
+	pub cwd: Option<String>,
+
+	/// Extra environment variables for the script
+	pub env: HashMap<String, String>,
+
+	/// The shell used to run the command
+	pub shell: Shell,
+
+	/// A short human readable description
+	pub description: Option<String>,
+
+	/// Globs of files the script reads. Scripts with
+	/// inputs are skipped when none of them changed.
+	pub inputs: Vec<String>,
+
+	/// Files and folders the script produces, cached
+	/// alongside the inputs
+	pub outputs: Vec<String>,
+
+	/// How long the script may run before its whole
+	/// process group is killed
+	pub timeout: Option<Duration>,
+
+	/// How many times to rerun the script if it fails
+	pub retries: u32,
+
+	/// Whether a failure is reported without stopping
+	/// the scripts that follow
+	pub allow_failure: bool
+}
+
+impl Script {
+	/// Create a script that only runs a command
+	#[inline]
+	#[must_use]
+	pub fn command(command: String) -> Self {
+		Self {
+			command: Some(command),
+			..Default::default()
+		}
+	}
+
+	/// Whether arguments passed to the script would never
+	/// reach its command, such as for compound shell
+	/// commands that don't read `$1`, `$@` and so on
+	#[inline]
+	#[must_use]
+	pub fn ignores_arguments(&self) -> bool {
+		match (&self.command, self.shell) {
+			(None, _) => true,
+			(Some(command), Shell::Sh | Shell::Bash) => shell_arguments(command) == ShellArguments::Unused,
+			(Some(_), Shell::Cmd | Shell::None) => false
+		}
+	}
+
+	/// Whether the script can be written in the
+	/// plain string form
+	fn is_plain(&self) -> bool {
+		self.command.is_some()
+			&& self.depends.is_empty()
+			&& self.cwd.is_none()
+			&& self.env.is_empty()
+			&& self.shell == Shell::default()
+			&& self.description.is_none()
+			&& self.inputs.is_empty()
+			&& self.outputs.is_empty()
+			&& self.timeout.is_none()
+			&& self.retries == 0
+			&& !self.allow_failure
+	}
+
+	/// Build the process for this script, run from the
+	/// given resource root. Any arguments are passed on
+	/// to the command as positional parameters, and are
+	/// only appended to it if it is a simple command.
+	/// The script's own environment is layered over `env`.
+	/// Returns `None` if the script has no command of
+	/// its own.
+	///
+	/// # Errors
+	///
+	/// Returns an error if a command run without a shell
+	/// is quoted wrong, or if `cwd` leads outside the root
+	#[inline]
+	pub fn process<S: ::std::hash::BuildHasher>(
+		&self,
+		root: &Path,
+		args: &[String],
+		env: &HashMap<String, String, S>
+	) -> Result<Option<Command>, ScriptProcessError> {
+		let Some(command) = self.command.as_ref() else {
+			return Ok(None);
+		};
+
+		let mut process = match self.shell {
+			Shell::Sh | Shell::Bash => {
+				let shell = if self.shell == Shell::Bash { "bash" } else { "sh" };
+				let mut process = Command::new(shell);
+
+				if args.is_empty() {
+					process.arg("-c").arg(command);
+				} else if shell_arguments(command) == ShellArguments::Appended {
+					// Arguments after the command string become $0, $1, ...
+					process
+						.arg("-c")
+						.arg(format!("{command} \"$@\""))
+						.arg(shell)
+						.args(args);
+				} else {
+					// Anything else gets the arguments as $1, ... only
+					process
+						.arg("-c")
+						.arg(command)
+						.arg(shell)
+						.args(args);
+				}
+
+				process
+			},
+			Shell::Cmd => {
+				let mut process = Command::new("cmd");
+				let mut line = command.clone();
+
+				for arg in args {
+					line.push(' ');
+					line.push_str(&cmd_quote(arg));
+				}
+
+				// cmd parses its own command line, so it has to
+				// get the quoted line as it is
+				#[cfg(windows)]
+				std::os::windows::process::CommandExt::raw_arg(process.arg("/C"), line);
+
+				#[cfg(not(windows))]
+				process.arg("/C").arg(line);
+
+				process
+			},
+			Shell::None => {
+				let words = shell_words::split(command)
+					.map_err(|_| ScriptProcessError::InvalidQuoting(command.clone()))?;
+				let mut words_iter = words.iter();
+
+				let Some(program) = words_iter.next() else {
+					return Ok(None);
+				};
+
+				let mut process = Command::new(program);
+				process.args(words_iter).args(args);
+				process
+			}
+		};
+
+		let cwd = match &self.cwd {
+			Some(cwd) if !super::link::is_valid_path(root, Path::new(cwd)) => {
+				return Err(ScriptProcessError::InvalidCwd(cwd.clone()));
+			},
+			Some(cwd) => root.join(cwd),
+			None => root.to_path_buf()
+		};
+
+		process
+			.current_dir(cwd)
+			.envs(env)
+			.envs(&self.env);
+
+		Ok(Some(process))
+	}
+}
+
+impl From<tomlconfig::Script0_2_2> for Script {
+	#[inline]
+	fn from(value: tomlconfig::Script0_2_2) -> Self {
+		match value {
+			tomlconfig::Script0_2_2::Command(command) => Self::command(command),
+			tomlconfig::Script0_2_2::Table(table) => Self {
+				command: table.cmd,
+				depends: table.depends.unwrap_or_default(),
+				cwd: table.cwd,
+				env: table.env.unwrap_or_default(),
+				shell: table.shell.map(Shell::from).unwrap_or_default(),
+				description: table.description,
+				inputs: table.inputs.unwrap_or_default(),
+				outputs: table.outputs.unwrap_or_default(),
+				timeout: table.timeout.map(Duration::from_secs),
+				retries: table.retries.unwrap_or_default(),
+				allow_failure: table.allow_failure.unwrap_or_default()
+			}
+		}
+	}
+}
+
+impl From<Script> for tomlconfig::Script0_2_2 {
+	#[inline]
+	fn from(value: Script) -> Self {
+		if value.is_plain() {
+			return Self::Command(value.command.unwrap_or_default());
+		}
+
+		let shell = Some(value.shell)
+			.filter(|shell| *shell != Shell::default())
+			.map(tomlconfig::Shell0_2_2::from);
+
+		Self::Table(tomlconfig::ScriptTable0_2_2 {
+			cmd: value.command,
+			depends: Some(value.depends).filter(|d| !d.is_empty()),
+			cwd: value.cwd,
+			env: tomlconfig::hashmap_to_option_hashmap(value.env),
+			shell,
+			description: value.description,
+			inputs: Some(value.inputs).filter(|i| !i.is_empty()),
+			outputs: Some(value.outputs).filter(|o| !o.is_empty()),
+			timeout: value.timeout.map(|timeout| timeout.as_secs()),
+			retries: Some(value.retries).filter(|r| *r > 0),
+			allow_failure: Some(value.allow_failure).filter(|a| *a)
+		})
+	}
+}
+
+/// A resource that defines scripts, like a repository or
+/// a workspace. Scripts run from the resource's path.
+pub trait ScriptHost: Resource {
+	/// Get the scripts hashmap
+	fn scripts(&self) -> HashMap<String, Script>;
+
+	/// Where runs of this resource's scripts are cached,
+	/// relative to the cache folder
+	fn cache_key(&self) -> PathBuf;
+
+	/// Get a specific script
+	#[inline]
+	#[must_use]
+	fn script(&self, name: &str) -> Option<Script> {
+		self.scripts().get(name).cloned()
+	}
+
+	/// Get the scripts that need to run for the given
+	/// script, dependencies first
+	///
+	/// # Errors
+	///
+	/// Returns an error if a script is missing or the
+	/// dependencies form a cycle
+	#[inline]
+	fn script_order(&self, name: &str) -> Result<Vec<String>, ScriptGraphError> {
+		execution_order(&self.scripts(), name)
+	}
+}
+
+/// A point in a repository's lifecycle where battalion
+/// runs scripts automatically
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Hook {
+	/// After `repository init`
+	PostInit,
+
+	/// After `repository scaffold` clones the git remote
+	PostScaffold,
+
+	/// After `repository fetch` unpacks the repository
+	PostFetch,
+
+	/// Before `repository publish` uploads, a failure
+	/// stops the upload
+	PrePublish,
+
+	/// Before `repository delete`, a failure stops the
+	/// deletion
+	PreDelete
+}
+
+impl Display for Hook {
+	#[inline]
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+		f.write_str(match self {
+			Self::PostInit => "post-init",
+			Self::PostScaffold => "post-scaffold",
+			Self::PostFetch => "post-fetch",
+			Self::PrePublish => "pre-publish",
+			Self::PreDelete => "pre-delete"
+		})
+	}
+}
+
+impl From<tomlconfig::Hook0_2_2> for Hook {
+	#[inline]
+	fn from(value: tomlconfig::Hook0_2_2) -> Self {
+		match value {
+			tomlconfig::Hook0_2_2::PostInit => Self::PostInit,
+			tomlconfig::Hook0_2_2::PostScaffold => Self::PostScaffold,
+			tomlconfig::Hook0_2_2::PostFetch => Self::PostFetch,
+			tomlconfig::Hook0_2_2::PrePublish => Self::PrePublish,
+			tomlconfig::Hook0_2_2::PreDelete => Self::PreDelete
+		}
+	}
+}
+
+impl From<Hook> for tomlconfig::Hook0_2_2 {
+	#[inline]
+	fn from(value: Hook) -> Self {
+		match value {
+			Hook::PostInit => Self::PostInit,
+			Hook::PostScaffold => Self::PostScaffold,
+			Hook::PostFetch => Self::PostFetch,
+			Hook::PrePublish => Self::PrePublish,
+			Hook::PreDelete => Self::PreDelete
+		}
+	}
+}
+
+/// The shell a script is run with
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Shell {
+	Sh,
+	Bash,
+	Cmd,
+
+	/// Run the command directly, without a shell
+	None
+}
+
+impl Default for Shell {
+	#[inline]
+	fn default() -> Self {
+		#[cfg(target_os = "windows")]
+		return Self::Cmd;
+
+		#[cfg(not(target_os = "windows"))]
+		return Self::Sh;
+	}
+}
+
+impl From<tomlconfig::Shell0_2_2> for Shell {
+	#[inline]
+	fn from(value: tomlconfig::Shell0_2_2) -> Self {
+		match value {
+			tomlconfig::Shell0_2_2::Sh => Self::Sh,
+			tomlconfig::Shell0_2_2::Bash => Self::Bash,
+			tomlconfig::Shell0_2_2::Cmd => Self::Cmd,
+			tomlconfig::Shell0_2_2::None => Self::None
+		}
+	}
+}
+
+impl From<Shell> for tomlconfig::Shell0_2_2 {
+	#[inline]
+	fn from(value: Shell) -> Self {
+		match value {
+			Shell::Sh => Self::Sh,
+			Shell::Bash => Self::Bash,
+			Shell::Cmd => Self::Cmd,
+			Shell::None => Self::None
+		}
+	}
+}
+
+/// What a shell command does with the arguments passed
+/// to a script
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ShellArguments {
+	/// A single command, so they are appended to it
+	Appended,
+
+	/// The command reads `$1`, `$@` and so on itself
+	Positional,
+
+	/// A compound command that never reads them
+	Unused
+}
+
+/// Work out how arguments reach a shell command. Commands
+/// that read their positional parameters get them as they
+/// are, single commands have them appended, and anything
+/// with control operators or comments can't use them.
+/// Quotes and substitutions are skipped over, so only
+/// what the shell itself would act on counts.
+fn shell_arguments(command: &str) -> ShellArguments {
+	let chars: Vec<char> = command.trim_end().chars().collect();
+
+	let mut compound = false;
+	let mut single = false;
+	let mut double = false;
+	let mut backtick = false;
+	let mut substitution = 0_usize;
+	let mut index = 0;
+
+	while let Some(&c) = chars.get(index) {
+		let previous = index.checked_sub(1).and_then(|i| chars.get(i)).copied();
+		let next = chars.get(index + 1).copied();
+
+		match c {
+			'\\' if !single => {
+				index += 2;
+				continue;
+			},
+			'\'' if !double => single = !single,
+			_ if single => {},
+			'"' => double = !double,
+			'`' => backtick = !backtick,
+			'$' => {
+				let parameter = if next == Some('{') { chars.get(index + 2).copied() } else { next };
+
+				if parameter.is_some_and(|p| p.is_ascii_digit() || matches!(p, '@' | '*' | '#')) {
+					return ShellArguments::Positional;
+				}
+
+				if next == Some('(') {
+					substitution += 1;
+					index += 2;
+					continue;
+				}
+			},
+			')' if substitution > 0 && !double => substitution -= 1,
+			_ if double || backtick || substitution > 0 => {},
+			';' | '|' | '(' | ')' | '\n' => compound = true,
+			'&' if !matches!(previous, Some('>' | '<')) && next != Some('>') => compound = true,
+			'#' if previous.map_or(true, char::is_whitespace) => compound = true,
+			_ => {}
+		}
+
+		index += 1;
+	}
+
+	if compound {
+		ShellArguments::Unused
+	} else {
+		ShellArguments::Appended
+	}
+}
+
+/// Quote an argument for `cmd`, which has no equivalent
+/// of `"$@"`. The argument is always quoted for the
+/// program, then every character `cmd` would act on is
+/// escaped, quotes included, so it can't start another
+/// command or expand variables.
+fn cmd_quote(arg: &str) -> String {
+	let mut quoted = String::from("\"");
+	let mut backslashes = 0;
+
+	for c in arg.chars() {
+		if c == '\\' {
+			backslashes += 1;
+			continue;
+		}
+
+		// Backslashes only escape when they come before a quote
+		let escapes = if c == '"' { backslashes * 2 + 1 } else { backslashes };
+		quoted.extend(std::iter::repeat('\\').take(escapes));
+		quoted.push(c);
+
+		backslashes = 0;
+	}
+
+	quoted.extend(std::iter::repeat('\\').take(backslashes * 2));
+	quoted.push('"');
+
+	let mut escaped = String::with_capacity(quoted.len() * 2);
+
+	for c in quoted.chars() {
+		if matches!(c, '^' | '&' | '|' | '<' | '>' | '%' | '!' | '(' | ')' | '"') {
+			escaped.push('^');
+		}
+
+		escaped.push(c);
+	}
+
+	escaped
+}
+
+/// Get every script that has to run for the named
+/// script, in the order they need to run. Each script
+/// is only included once, even if several scripts
+/// depend on it.
+///
+/// # Errors
+///
+/// Returns an error if any script in the graph does not
+/// exist, or if the dependencies form a cycle.
+#[inline]
+pub fn execution_order<S: ::std::hash::BuildHasher>(
+	scripts: &HashMap<String, Script, S>,
+	name: &str
+) -> Result<Vec<String>, ScriptGraphError> {
+	let mut order = Vec::new();
+	let mut stack = Vec::new();
+
+	visit(scripts, name, &mut stack, &mut order)?;
+
+	Ok(order)
+}
+
+/// Depth first visit for `execution_order`
+fn visit<S: ::std::hash::BuildHasher>(
+	scripts: &HashMap<String, Script, S>,
+	name: &str,
+	stack: &mut Vec<String>,
+	order: &mut Vec<String>
+) -> Result<(), ScriptGraphError> {
+	if order.iter().any(|done| done == name) {
+		return Ok(());
+	}
+
+	if let Some(start) = stack.iter().position(|visiting| visiting == name) {
+		let mut cycle = stack.get(start..).unwrap_or_default().to_vec();
+		cycle.push(name.to_owned());
+
+		return Err(ScriptGraphError::Cycle(cycle));
+	}
+
+	let script = scripts.get(name)
+		.ok_or_else(|| ScriptGraphError::NotFound(name.to_owned()))?;
+
+	stack.push(name.to_owned());
+
+	for dependency in &script.depends {
+		visit(scripts, dependency, stack, order)?;
+	}
+
+	stack.pop();
+	order.push(name.to_owned());
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn scripts(graph: &[(&str, &[&str])]) -> HashMap<String, Script> {
+		graph
+			.iter()
+			.map(|(name, depends)| {
+				let script = Script {
+					depends: depends.iter().map(ToString::to_string).collect(),
+					..Script::command(format!("echo {name}"))
+				};
+
+				(name.to_string(), script)
+			})
+			.collect()
+	}
+
+	#[test]
+	fn orders_dependencies_first() {
+		let scripts = scripts(&[("build", &["gen"]), ("gen", &[])]);
+
+		assert_eq!(execution_order(&scripts, "build").unwrap(), ["gen", "build"]);
+	}
+
+	#[test]
+	fn runs_shared_dependencies_once() {
+		let scripts = scripts(&[
+			("all", &["lint", "test"]),
+			("lint", &["gen"]),
+			("test", &["gen"]),
+			("gen", &[])
+		]);
+
+		assert_eq!(execution_order(&scripts, "all").unwrap(), ["gen", "lint", "test", "all"]);
+	}
+
+	#[test]
+	fn reports_cycles() {
+		let scripts = scripts(&[("a", &["b"]), ("b", &["c"]), ("c", &["a"])]);
+
+		match execution_order(&scripts, "a") {
+			Err(ScriptGraphError::Cycle(cycle)) => assert_eq!(cycle, ["a", "b", "c", "a"]),
+			other => panic!("expected a cycle, got {other:?}")
+		}
+	}
+
+	#[test]
+	fn appends_arguments_to_single_commands() {
+		for command in [
+			"cargo test",
+			"echo ${BATL_REPOSITORY_NAME}",
+			"echo $HOME",
+			"echo out > f",
+			"echo warn >&2",
+			"echo $(date)",
+			"echo 'a;b' \"c|d\"",
+			"echo a#b"
+		] {
+			assert_eq!(shell_arguments(command), ShellArguments::Appended, "{command}");
+		}
+	}
+
+	#[test]
+	fn passes_positional_parameters_to_commands_that_read_them() {
+		for command in ["echo $1", "echo \"$@\"", "echo $*", "echo $#", "echo ${1}", "a && echo ${@}"] {
+			assert_eq!(shell_arguments(command), ShellArguments::Positional, "{command}");
+		}
+	}
+
+	#[test]
+	fn ignores_quoted_parameters() {
+		assert_eq!(shell_arguments("echo '$1'"), ShellArguments::Appended);
+		assert_eq!(shell_arguments("echo \\$1"), ShellArguments::Appended);
+	}
+
+	#[test]
+	fn leaves_arguments_off_compound_commands() {
+		for command in ["a && b", "a; b;", "a | b", "a & b", "(a)", "a\nb", "echo hi # note"] {
+			assert_eq!(shell_arguments(command), ShellArguments::Unused, "{command}");
+		}
+	}
+
+	#[test]
+	fn quotes_cmd_arguments() {
+		assert_eq!(cmd_quote("plain"), "^\"plain^\"");
+		assert_eq!(cmd_quote("a&b"), "^\"a^&b^\"");
+		assert_eq!(cmd_quote("x|del y"), "^\"x^|del y^\"");
+		assert_eq!(cmd_quote("%PATH%!"), "^\"^%PATH^%^!^\"");
+		assert_eq!(cmd_quote("say \"hi\""), "^\"say \\^\"hi\\^\"^\"");
+		assert_eq!(cmd_quote("dir\\"), "^\"dir\\\\^\"");
+	}
+
+	#[test]
+	fn reports_missing_scripts() {
+		let scripts = scripts(&[("build", &["gen"])]);
+
+		assert!(matches!(execution_order(&scripts, "build"), Err(ScriptGraphError::NotFound(name)) if name == "gen"));
+		assert!(matches!(execution_order(&scripts, "deploy"), Err(ScriptGraphError::NotFound(name)) if name == "deploy"));
+	}
+}
@@ -17,6 +17,7 @@ pub type ScriptsLatest = Scripts0_2_2;
 pub type DependenciesLatest = Dependencies0_2_2;
 pub type RestrictLatest = Restrict0_2_2;
 pub type RestrictorLatest = Restrictor0_2_2;
+pub type HooksLatest = Hooks0_2_2;
 
 environment_struct_impl!("0.2.0");
 environment_struct_impl!("0.2.1");
@@ -35,11 +36,93 @@ pub struct Workspace0_2_2 {
 	pub version: semver::Version
 }
 
-pub type Links0_2_2 = Links0_2_1;
+pub type Links0_2_2 = HashMap<String, Link0_2_2>;
 pub type RepositoryGit0_2_2 = RepositoryGit0_2_1;
-pub type Scripts0_2_2 = Scripts0_2_1;
+pub type Scripts0_2_2 = HashMap<String, Script0_2_2>;
 pub type Dependencies0_2_2 = Dependencies0_2_1;
 pub type Restrict0_2_2 = HashMap<Restrictor0_2_2, RestrictorSettings0_2_2>;
+pub type Hooks0_2_2 = HashMap<Hook0_2_2, Vec<String>>;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Link0_2_2 {
+	Repository(Name),
+	Table(LinkTable0_2_2)
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct LinkTable0_2_2 {
+	pub repository: Name,
+	pub mode: Option<LinkMode0_2_2>,
+	pub branch: Option<String>,
+	pub path: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkMode0_2_2 {
+	Symlink,
+	Worktree,
+	Copy,
+	HardlinkTree,
+	Readonly
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Script0_2_2 {
+	Command(String),
+	#[serde(deserialize_with = "script_table")]
+	Table(ScriptTable0_2_2)
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptTable0_2_2 {
+	pub cmd: Option<String>,
+	pub depends: Option<Vec<String>>,
+	pub cwd: Option<String>,
+	pub env: Option<HashMap<String, String>>,
+	pub shell: Option<Shell0_2_2>,
+	pub description: Option<String>,
+	pub inputs: Option<Vec<String>>,
+	pub outputs: Option<Vec<String>>,
+	pub timeout: Option<u64>,
+	pub retries: Option<u32>,
+	pub allow_failure: Option<bool>
+}
+
+/// Reads a script table, which has to either run a
+/// command or depend on other scripts
+fn script_table<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<ScriptTable0_2_2, D::Error> {
+	let table = ScriptTable0_2_2::deserialize(deserializer)?;
+
+	if table.cmd.is_none() && table.depends.is_none() {
+		return Err(serde::de::Error::custom("script needs a cmd or depends"));
+	}
+
+	Ok(table)
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Shell0_2_2 {
+	Sh,
+	Bash,
+	Cmd,
+	None
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum Hook0_2_2 {
+	PostInit,
+	PostScaffold,
+	PostFetch,
+	PrePublish,
+	PreDelete
+}
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub enum Restrictor0_2_2 {
@@ -111,6 +194,78 @@ pub fn write_toml<T: serde::Serialize>(path: &Path, data: &T) -> Result<(), std:
 	Ok(())
 }
 
+/// Writes a toml struct to a path, making sure only the
+/// current user can read it. Use this for anything secret.
+///
+/// # Errors
+///
+/// Propogates any IO errors received while writing the file,
+/// and fails without touching it if `data` can't be serialized.
+#[inline]
+pub fn write_toml_private<T: serde::Serialize>(path: &Path, data: &T) -> Result<(), std::io::Error> {
+	// Serialize first so a failure can't leave the file truncated
+	let contents = toml::to_string(data)
+		.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+	let mut options = std::fs::OpenOptions::new();
+	options.write(true).create(true).truncate(true);
+
+	#[cfg(unix)]
+	std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+	let mut file = options.open(path)?;
+
+	// The mode only applies to new files, so fix up old ones too
+	#[cfg(unix)]
+	std::fs::set_permissions(path, std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+
+	file.write_all(contents.as_bytes())?;
+
+	Ok(())
+}
+
+/// Upgrades plain string scripts to the current script format
+#[inline]
+#[must_use]
+pub fn upgrade_scripts_0_2_1(scripts: Scripts0_2_1) -> Scripts0_2_2 {
+	scripts.into_iter()
+		.map(|(k, v)| (k, Script0_2_2::Command(v)))
+		.collect()
+}
+
+/// Upgrades plain links, which were always symlinks, to
+/// the current link format
+#[inline]
+#[must_use]
+pub fn upgrade_links_0_2_1(links: Links0_2_1) -> Links0_2_2 {
+	links.into_iter()
+		.map(|(k, v)| (k, Link0_2_2::Repository(v)))
+		.collect()
+}
+
+/// Serializes an optional hashmap with its keys in order,
+/// so files written from it diff cleanly
+///
+/// # Errors
+///
+/// Propogates any errors from the serializer
+#[inline]
+pub fn sorted_map<K, V, H, S>(map: &Option<HashMap<K, V, H>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+	K: Serialize + core::fmt::Display,
+	V: Serialize,
+	S: serde::Serializer
+{
+	let Some(map) = map else {
+		return serializer.serialize_none();
+	};
+
+	let mut entries: Vec<(&K, &V)> = map.iter().collect();
+	entries.sort_by_cached_key(|(key, _)| key.to_string());
+
+	serializer.collect_map(entries)
+}
+
 /// Returns `None` if a hashmap is empty
 #[inline]
 #[must_use]
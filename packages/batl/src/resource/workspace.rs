@@ -1,10 +1,13 @@
+use core::fmt::{Display, Formatter};
 use crate::error as batlerror;
 use semver::Version;
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use super::{tomlconfig, Name, Resource};
+use super::link::{self, Link, LinkMode};
 use super::repository::Repository;
+use super::script::{Script, ScriptHost};
 use super::tomlconfig::TomlConfig;
 
 
@@ -79,6 +82,165 @@ impl Workspace {
 		})
 	}
 
+	/// Creates a workspace from an existing configuration,
+	/// such as one from a manifest. Links are left out,
+	/// since they need their repositories in place first.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors received during creation,
+	/// including if the workspace already exists.
+	#[inline]
+	pub fn create_from(mut toml: TomlConfigLatest) -> Result<Self, batlerror::CreateResourceError> {
+		let name = toml.workspace.name.clone();
+
+		let path = crate::system::workspace_root()
+			.ok_or(batlerror::CreateResourceError::NotSetup)?
+			.join(PathBuf::from(&name));
+
+		if path.join("batl.toml").exists() {
+			return Err(batlerror::CreateResourceError::AlreadyExists);
+		}
+
+		std::fs::create_dir_all(&path)?;
+
+		toml.environment = tomlconfig::EnvironmentLatest::default();
+		toml.links = None;
+
+		tomlconfig::write_toml(&path.join("batl.toml"), &toml)?;
+
+		Ok(Self {
+			path,
+			config: toml.into(),
+			name
+		})
+	}
+
+	/// Move the workspace to a new name. This moves its
+	/// folder and updates the name in its config, along
+	/// with the git worktrees of any worktree links.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors received in the process,
+	/// including if a workspace with the new name exists
+	#[inline]
+	pub fn rename(&mut self, name: Name) -> Result<(), batlerror::CreateResourceError> {
+		let root = crate::system::workspace_root()
+			.ok_or(batlerror::CreateResourceError::NotSetup)?;
+
+		let path = root.join(PathBuf::from(&name));
+
+		if path.exists() {
+			return Err(batlerror::CreateResourceError::AlreadyExists);
+		}
+
+		super::move_resource(&self.path, &path, &root)?;
+
+		let previous = self.config.name.clone();
+
+		self.path = path;
+		self.name = name.clone();
+		self.config.name = name;
+
+		self.save()?;
+
+		for (link_name, link) in &self.config.links {
+			if let LinkMode::Worktree { .. } = link.mode {
+				if let Some(repo) = Repository::load(link.repository.clone()).ok().flatten() {
+					self.repair_worktree(link_name, &repo, &worktree_name(&previous, link_name))?;
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Point links and dependencies on a renamed
+	/// repository at its new name, fixing up links on
+	/// disk that lead to its old folder. Returns whether
+	/// anything changed.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors received in the process
+	#[inline]
+	pub fn rename_repository(&mut self, old: &Name, repo: &Repository) -> Result<bool, batlerror::CreateResourceError> {
+		let new = repo.name();
+		let mut changed = super::repository::rename_key(&mut self.config.dependencies, old, new);
+
+		let mut names: Vec<String> = self.config.links
+			.iter()
+			.filter(|(_, link)| link.repository == *old)
+			.map(|(name, _)| name.clone())
+			.collect();
+
+		names.sort();
+
+		for name in names {
+			let Some(link) = self.config.links.get_mut(&name) else {
+				continue;
+			};
+
+			link.repository = new.clone();
+			let link = link.clone();
+
+			changed = true;
+
+			match link.mode {
+				LinkMode::Symlink => {
+					let path = self.path.join(&name);
+
+					if std::fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_symlink()) {
+						std::fs::remove_file(&path)?;
+					}
+
+					self.place_link(&name, &link, repo)?;
+				},
+				LinkMode::Worktree { .. } => {
+					self.repair_worktree(&name, repo, &worktree_name(&self.config.name, &name))?;
+				},
+				LinkMode::Copy | LinkMode::HardlinkTree | LinkMode::Readonly => {}
+			}
+		}
+
+		if changed {
+			self.save()?;
+		}
+
+		Ok(changed)
+	}
+
+	/// Describe the workspace and every repository it
+	/// links, so it can be recreated somewhere else.
+	/// Repositories that can't be loaded are listed
+	/// without a version.
+	#[inline]
+	#[must_use]
+	pub fn manifest(&self) -> ManifestLatest {
+		let mut repositories = HashMap::new();
+
+		for name in self.links().into_values() {
+			let repository = Repository::load(name.clone()).ok().flatten();
+
+			repositories.insert(name, ManifestRepository0_2_2 {
+				version: repository.as_ref().map(|repo| repo.config().version.clone()),
+				git: repository
+					.and_then(|repo| repo.config().git.clone())
+					.map(|git| tomlconfig::RepositoryGit0_2_2 {
+						url: git.url,
+						path: git.path
+					})
+			});
+		}
+
+		ManifestLatest {
+			environment: tomlconfig::EnvironmentLatest::default(),
+			config: self.config.clone().into(),
+			repositories: tomlconfig::hashmap_to_option_hashmap(repositories)
+		}
+	}
+
 	/// Saves the workspace to the local filesystem
 	/// 
 	/// # Errors
@@ -129,19 +291,111 @@ impl Workspace {
 	#[inline]
 	#[must_use]
 	pub fn links(&self) -> HashMap<String, Name> {
-		self.config.links.clone()
+		self.config.links
+			.iter()
+			.map(|(name, link)| (name.clone(), link.repository.clone()))
+			.collect()
+	}
+
+	/// Get the full configuration of a link, including how
+	/// it is placed in the workspace
+	#[inline]
+	#[must_use]
+	pub fn link_config(&self, name: &str) -> Option<Link> {
+		self.config.links.get(name).cloned()
 	}
 
 	/// Get the link with the specific name, if it
 	/// exists. This also resolves the repository from
 	/// the filesystem. If the repository or link does
 	/// not exist, this will return `None`.
+	///
+	/// Symlinked repositories and links to a folder inside
+	/// a repository load from the repository folder, other
+	/// links load from the workspace so scripts run
+	/// against the workspace's copy.
 	#[inline]
 	#[must_use]
 	pub fn link(&self, name: &str) -> Option<Repository> {
-		let res_name = self.links().get(name)?.clone();
+		let link = self.config.links.get(name)?;
+
+		if link.mode == LinkMode::Symlink || link.path.is_some() {
+			Repository::load(link.repository.clone()).ok().flatten()
+		} else {
+			Repository::from_path_named(link.repository.clone(), &self.path.join(name)).ok()
+		}
+	}
+
+	/// Get the folder commands and scripts for a link run
+	/// in, if it isn't the linked repository's own folder.
+	/// This is the case for links to a folder inside a
+	/// repository.
+	#[inline]
+	#[must_use]
+	pub fn link_cwd(&self, name: &str) -> Option<PathBuf> {
+		self.config.links
+			.get(name)?
+			.path
+			.as_ref()
+			.map(|_| self.path.join(name))
+	}
+
+	/// Get the links each link depends on, based on the
+	/// `dependencies` of the linked repositories. Only
+	/// dependencies that are linked in this workspace are
+	/// included, and links that can't be loaded have no
+	/// dependencies.
+	#[inline]
+	#[must_use]
+	pub fn link_dependencies(&self) -> HashMap<String, Vec<String>> {
+		let links = self.links();
+
+		links.keys()
+			.map(|link| {
+				let mut depends: Vec<String> = self.link(link)
+					.map(|repo| {
+						repo.config().dependencies
+							.keys()
+							.flat_map(|dep| {
+								links.iter()
+									.filter(move |(_, name)| *name == dep)
+									.map(|(other, _)| other.clone())
+							})
+							.filter(|other| other != link)
+							.collect()
+					})
+					.unwrap_or_default();
+
+				depends.sort();
+
+				(link.clone(), depends)
+			})
+			.collect()
+	}
+
+	/// Get the environment variables scripts run with
+	/// when they run under this workspace. Each link gets
+	/// a `BATL_LINK_<NAME>` variable with its path.
+	#[inline]
+	#[must_use]
+	pub fn script_env(&self) -> HashMap<String, String> {
+		let mut env = HashMap::new();
+
+		if let Some(batl_root) = crate::system::batl_root() {
+			env.insert("BATL_ROOT".to_owned(), batl_root.to_string_lossy().to_string());
+		}
+
+		env.insert("BATL_WORKSPACE".to_owned(), self.path.to_string_lossy().to_string());
+		env.insert("BATL_WORKSPACE_NAME".to_owned(), self.config.name.to_string());
 
-		Repository::load(res_name).ok().flatten()
+		for link in self.config.links.keys() {
+			env.insert(
+				format!("BATL_LINK_{}", crate::system::env_var_name(link)),
+				self.path.join(link).to_string_lossy().to_string()
+			);
+		}
+
+		env
 	}
 
 	/// Given a name and repository, create a workspace
@@ -153,42 +407,301 @@ impl Workspace {
 	/// Returns any errors received in the process.
 	#[inline]
 	pub fn create_link(&mut self, name: &str, repo: &Repository) -> Result<(), batlerror::CreateResourceError> {
-		let mut links = self.links();
+		self.create_link_with(name, repo, LinkMode::Symlink, None)
+	}
 
-		if links.contains_key(name) {
+	/// Create a workspace link that places the repository
+	/// in the workspace with the given mode. With a path,
+	/// the link points at that folder of the repository
+	/// instead, which has to stay inside the repository.
+	/// Worktree and read-only links always hold the whole
+	/// repository.
+	/// 
+	/// # Errors
+	/// 
+	/// Returns any errors received in the process.
+	#[inline]
+	pub fn create_link_with(&mut self, name: &str, repo: &Repository, mode: LinkMode, path: Option<PathBuf>) -> Result<(), batlerror::CreateResourceError> {
+		if self.config.links.contains_key(name) {
 			return Err(batlerror::CreateResourceError::AlreadyExists);
 		}
 
-		links.insert(name.to_owned(), repo.name().clone());
-		self.config.links = links;
+		if let Some(path) = &path {
+			let supported = matches!(mode, LinkMode::Symlink | LinkMode::Copy | LinkMode::HardlinkTree);
+
+			if !supported || !link::is_valid_path(repo.path(), path) {
+				return Err(batlerror::CreateResourceError::InvalidPath(path.display().to_string()));
+			}
+		}
+
+		let link = Link {
+			repository: repo.name().clone(),
+			mode,
+			path
+		};
+
+		let existed = std::fs::symlink_metadata(self.path.join(name)).is_ok();
 
-		super::symlink_dir(repo.path(), &self.path.join(name))?;
+		if let Err(err) = self.place_link(name, &link, repo) {
+			// Never clear away something that was already there
+			if !existed {
+				self.discard_link(name, &link, repo);
+			}
 
+			return Err(err);
+		}
+
+		self.config.links.insert(name.to_owned(), link);
 		self.save()?;
 
 		Ok(())
 	}
 
+	/// Clear away whatever a failed `place_link` left on
+	/// disk. Errors are ignored, since the failure that got
+	/// us here is the one worth reporting.
+	fn discard_link(&self, name: &str, link: &Link, repo: &Repository) {
+		let path = self.path.join(name);
+
+		if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+			if metadata.is_dir() {
+				let _ = link::set_readonly(&path, false);
+				let _ = std::fs::remove_dir_all(&path);
+			} else {
+				let _ = std::fs::remove_file(&path);
+			}
+		}
+
+		if let LinkMode::Worktree { .. } = link.mode {
+			let git = repo.git_checkout().and_then(|checkout| git2::Repository::open(checkout).ok());
+
+			if let Some(worktree) = git.and_then(|git| git.find_worktree(&worktree_name(&self.config.name, name)).ok()) {
+				let _ = worktree.prune(None);
+			}
+		}
+	}
+
+	/// Put a link on disk
+	fn place_link(&self, name: &str, link: &Link, repo: &Repository) -> Result<(), batlerror::CreateResourceError> {
+		let path = self.path.join(name);
+
+		match &link.mode {
+			LinkMode::Symlink => super::symlink_dir(&link.target(repo.path()), &path)?,
+			LinkMode::Worktree { branch } => self.place_worktree(name, repo, branch)?,
+			LinkMode::Copy => link::materialize(&link.target(repo.path()), &path, false)?,
+			LinkMode::HardlinkTree => link::materialize(&link.target(repo.path()), &path, true)?,
+			LinkMode::Readonly => {
+				let mut archive = repo.archive()
+					.ok_or(batlerror::CreateResourceError::NoArchive)?;
+
+				std::fs::create_dir_all(&path)?;
+				archive.tar.unpack(&path)?;
+
+				link::set_readonly(&path, true)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Refresh a link that holds its own files from its
+	/// repository. Returns `false` for links that always
+	/// see the repository as it is, like symlinks and
+	/// worktrees.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors received in the process
+	#[inline]
+	pub fn sync_link(&self, name: &str) -> Result<bool, batlerror::CreateDependentResourceError> {
+		let Some(config) = self.link_config(name) else {
+			return Ok(false);
+		};
+
+		if !config.mode.is_materialized() {
+			return Ok(false);
+		}
+
+		let repo = Repository::load(config.repository.clone())?
+			.ok_or(batlerror::GeneralResourceError::DoesNotExist)?;
+
+		let path = self.path.join(name);
+
+		if path.exists() {
+			link::set_readonly(&path, false)?;
+			std::fs::remove_dir_all(&path)?;
+		}
+
+		self.place_link(name, &config, &repo)?;
+
+		Ok(true)
+	}
+
+	/// Mirror a repository into the workspace, replacing
+	/// its git checkout with a worktree on the given
+	/// branch. The branch is created from the checkout's
+	/// `HEAD` if it doesn't exist yet.
+	fn place_worktree(&self, name: &str, repo: &Repository, branch: &str) -> Result<(), batlerror::CreateResourceError> {
+		let checkout = repo.git_checkout()
+			.ok_or(batlerror::CreateResourceError::NoGitCheckout)?;
+
+		let git = git2::Repository::open(&checkout)?;
+
+		let link_path = self.path.join(name);
+		let checkout_rel = pathdiff::diff_paths(&checkout, repo.path()).unwrap_or_default();
+
+		share_repository_entries(&link_path, repo, &checkout_rel)?;
+
+		let worktree_path = link_path.join(&checkout_rel);
+
+		if let Some(parent) = worktree_path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+
+		let worktree = worktree_name(&self.config.name, name);
+
+		// Clear out anything left behind by a worktree that
+		// was deleted by hand
+		if let Ok(stale) = git.find_worktree(&worktree) {
+			if stale.validate().is_err() {
+				stale.prune(None)?;
+			}
+		}
+
+		let branch_ref = match git.find_branch(branch, git2::BranchType::Local) {
+			Ok(found) => found,
+			Err(_) => git.branch(branch, &git.head()?.peel_to_commit()?, false)?
+		};
+
+		let mut options = git2::WorktreeAddOptions::new();
+		options.reference(Some(branch_ref.get()));
+
+		git.worktree(&worktree, &worktree_path, Some(&options))?;
+
+		Ok(())
+	}
+
+	/// Point a worktree link and the repository's checkout
+	/// back at each other after either one moved. The
+	/// worktree is renamed from `previous` to its current
+	/// name if they differ.
+	fn repair_worktree(&self, name: &str, repo: &Repository, previous: &str) -> Result<(), batlerror::CreateResourceError> {
+		let checkout = repo.git_checkout()
+			.ok_or(batlerror::CreateResourceError::NoGitCheckout)?;
+
+		let git = git2::Repository::open(&checkout)?;
+
+		let link_path = self.path.join(name);
+		let checkout_rel = pathdiff::diff_paths(&checkout, repo.path()).unwrap_or_default();
+		let worktree_path = link_path.join(&checkout_rel);
+
+		share_repository_entries(&link_path, repo, &checkout_rel)?;
+
+		let admin_root = git.path().join("worktrees");
+		let admin_path = admin_root.join(worktree_name(&self.config.name, name));
+
+		if admin_root.join(previous) != admin_path {
+			std::fs::rename(admin_root.join(previous), &admin_path)?;
+		}
+
+		std::fs::write(admin_path.join("commondir"), format!("{}\n", git.path().display()))?;
+		std::fs::write(admin_path.join("gitdir"), format!("{}\n", worktree_path.join(".git").display()))?;
+		std::fs::write(worktree_path.join(".git"), format!("gitdir: {}\n", admin_path.display()))?;
+
+		Ok(())
+	}
+
 	/// Delete a repository link by name. This removes
-	/// the entry and the symlink from the workspace.
+	/// the entry and the link from the workspace.
+	/// Worktrees are only removed if they have no
+	/// uncommitted changes, and are then pruned from the
+	/// repository's checkout.
 	/// 
 	/// # Errors
 	/// 
 	/// Propogates any errors returned to the caller.
 	#[inline]
 	pub fn unlink(&mut self, name: &str) -> Result<(), batlerror::DeleteResourceError> {
-		let mut links = self.links();
+		let link = self.config.links
+			.get(name)
+			.cloned()
+			.ok_or(batlerror::DeleteResourceError::DoesNotExist)?;
+
+		self.remove_link(name, &link)?;
+
+		self.config.links.remove(name);
+		self.save()?;
+
+		Ok(())
+	}
+
+	/// Take a link off the disk
+	fn remove_link(&self, name: &str, link: &Link) -> Result<(), batlerror::DeleteResourceError> {
+		let path = self.path.join(name);
 
-		if !links.contains_key(name) {
-			return Err(batlerror::DeleteResourceError::DoesNotExist);
+		match link.mode {
+			LinkMode::Symlink => std::fs::remove_file(path)?,
+			LinkMode::Worktree { .. } => self.remove_worktree(name, link)?,
+			LinkMode::Copy | LinkMode::HardlinkTree | LinkMode::Readonly => {
+				if path.exists() {
+					link::set_readonly(&path, false)?;
+					std::fs::remove_dir_all(path)?;
+				}
+			}
 		}
 
-		links.remove(name);
-		self.config.links = links;
+		Ok(())
+	}
 
-		std::fs::remove_file(self.path.join(name))?;
+	/// Make sure a worktree link has nothing that would be
+	/// lost by removing it
+	fn check_worktree_clean(&self, name: &str, link: &Link) -> Result<(), batlerror::DeleteResourceError> {
+		let Some(repo) = Repository::load(link.repository.clone()).ok().flatten() else {
+			return Ok(());
+		};
 
-		self.save()?;
+		let Some(checkout) = repo.git_checkout() else {
+			return Ok(());
+		};
+
+		let checkout_rel = pathdiff::diff_paths(&checkout, repo.path()).unwrap_or_default();
+		let worktree_path = self.path.join(name).join(checkout_rel);
+
+		if let Ok(worktree) = git2::Repository::open(&worktree_path) {
+			let mut options = git2::StatusOptions::new();
+			options.include_untracked(true).include_ignored(false);
+
+			if !worktree.statuses(Some(&mut options))?.is_empty() {
+				return Err(batlerror::DeleteResourceError::UncommittedChanges);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Remove a worktree link and prune it from the
+	/// repository's checkout
+	fn remove_worktree(&self, name: &str, link: &Link) -> Result<(), batlerror::DeleteResourceError> {
+		let link_path = self.path.join(name);
+
+		self.check_worktree_clean(name, link)?;
+
+		let checkout = Repository::load(link.repository.clone())
+			.ok()
+			.flatten()
+			.and_then(|repo| repo.git_checkout());
+
+		if std::fs::symlink_metadata(&link_path).is_ok() {
+			std::fs::remove_dir_all(&link_path)?;
+		}
+
+		if let Some(checkout) = checkout {
+			let git = git2::Repository::open(checkout)?;
+
+			if let Ok(worktree) = git.find_worktree(&worktree_name(&self.config.name, name)) {
+				worktree.prune(None)?;
+			}
+		}
 
 		Ok(())
 	}
@@ -197,13 +710,244 @@ impl Workspace {
 	/// 
 	/// # Errors
 	/// 
-	/// Returns any errors back to the caller.
+	/// Returns any errors back to the caller, including if
+	/// a worktree link has uncommitted changes, in which
+	/// case nothing is removed.
 	#[inline]
 	pub fn destroy(self) -> Result<(), batlerror::DeleteResourceError> {
+		// Nothing is removed unless every worktree is clean
+		for (name, link) in &self.config.links {
+			if let LinkMode::Worktree { .. } = link.mode {
+				self.check_worktree_clean(name, link)?;
+			}
+		}
+
+		for (name, link) in &self.config.links {
+			if link.mode == LinkMode::Readonly {
+				link::set_readonly(&self.path.join(name), false)?;
+			}
+		}
+
 		std::fs::remove_dir_all(self.path())?;
 
+		// Worktrees are gone with the workspace, so only
+		// their records in each checkout are left
+		for (name, link) in &self.config.links {
+			if let LinkMode::Worktree { .. } = link.mode {
+				let checkout = Repository::load(link.repository.clone())
+					.ok()
+					.flatten()
+					.and_then(|repo| repo.git_checkout());
+
+				if let Some(worktree) = checkout
+					.and_then(|checkout| git2::Repository::open(checkout).ok())
+					.and_then(|git| git.find_worktree(&worktree_name(&self.config.name, name)).ok())
+				{
+					worktree.prune(None)?;
+				}
+			}
+		}
+
 		Ok(())
 	}
+
+	/// Compare the configured links with what is on disk.
+	/// Hidden entries and battalion files are never
+	/// considered untracked.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors from reading the workspace
+	#[inline]
+	pub fn diagnose(&self) -> Result<Vec<LinkProblem>, std::io::Error> {
+		let links = self.links();
+		let mut problems = Vec::new();
+
+		let mut names: Vec<&String> = links.keys().collect();
+		names.sort();
+
+		for link in names {
+			let path = self.path.join(link);
+
+			let Some(repo) = Repository::load(links[link].clone()).ok().flatten() else {
+				problems.push(LinkProblem::Unloadable(link.clone(), links[link].clone()));
+				continue;
+			};
+
+			let Ok(metadata) = std::fs::symlink_metadata(&path) else {
+				problems.push(LinkProblem::Missing(link.clone()));
+				continue;
+			};
+
+			if self.config.links[link].mode != LinkMode::Symlink {
+				if !metadata.is_dir() {
+					problems.push(LinkProblem::Obstructed(link.clone()));
+				}
+
+				continue;
+			}
+
+			if !metadata.file_type().is_symlink() {
+				problems.push(LinkProblem::Obstructed(link.clone()));
+				continue;
+			}
+
+			let target = std::fs::read_link(&path)?;
+
+			if !target.exists() {
+				problems.push(LinkProblem::Dangling(link.clone()));
+			} else if target != self.config.links[link].target(repo.path()) {
+				problems.push(LinkProblem::WrongTarget(link.clone(), target));
+			}
+		}
+
+		let mut untracked = Vec::new();
+
+		for entry in std::fs::read_dir(&self.path)? {
+			let entry = entry?;
+			let name = entry.file_name().to_string_lossy().into_owned();
+
+			if name.starts_with('.') || name.starts_with("batl.") || links.contains_key(&name) {
+				continue;
+			}
+
+			let is_symlink = entry.file_type()?.is_symlink();
+
+			untracked.push(LinkProblem::Untracked(name, is_symlink));
+		}
+
+		untracked.sort_by_key(ToString::to_string);
+		problems.extend(untracked);
+
+		Ok(problems)
+	}
+
+	/// Repair a problem found by `diagnose`. Links to
+	/// repositories that no longer load and untracked
+	/// symlinks are pruned, other links are recreated.
+	/// Returns `false` for problems that need a person to
+	/// look at them, like real files where a link should be.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO or git errors
+	#[inline]
+	pub fn repair(&mut self, problem: &LinkProblem) -> Result<bool, batlerror::CreateResourceError> {
+		match problem {
+			LinkProblem::Unloadable(link, _) => {
+				let path = self.path.join(link);
+
+				if std::fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_symlink()) {
+					std::fs::remove_file(&path)?;
+				}
+
+				self.config.links.remove(link);
+				self.save()?;
+			},
+			LinkProblem::Missing(link) | LinkProblem::Dangling(link) | LinkProblem::WrongTarget(link, _) => {
+				let Some(config) = self.link_config(link) else {
+					return Ok(false);
+				};
+
+				let Some(repo) = Repository::load(config.repository.clone()).ok().flatten() else {
+					return Ok(false);
+				};
+
+				let path = self.path.join(link);
+
+				if std::fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_symlink()) {
+					std::fs::remove_file(&path)?;
+				}
+
+				self.place_link(link, &config, &repo)?;
+			},
+			LinkProblem::Untracked(name, true) => {
+				std::fs::remove_file(self.path.join(name))?;
+			},
+			LinkProblem::Obstructed(_) | LinkProblem::Untracked(_, false) => {
+				return Ok(false);
+			}
+		}
+
+		Ok(true)
+	}
+}
+
+/// The name git knows a link's worktree by
+fn worktree_name(workspace: &Name, link: &str) -> String {
+	format!("batl-{}-{}", workspace, link)
+		.chars()
+		.map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+		.collect()
+}
+
+/// Symlink everything in a repository outside of its git
+/// checkout into a worktree link, replacing any symlinks
+/// already there
+fn share_repository_entries(link_path: &Path, repo: &Repository, checkout_rel: &Path) -> Result<(), std::io::Error> {
+	let Some(checkout_root) = checkout_rel.components().next() else {
+		return Ok(());
+	};
+
+	std::fs::create_dir_all(link_path)?;
+
+	for entry in std::fs::read_dir(repo.path())? {
+		let entry = entry?;
+
+		if entry.file_name() == checkout_root.as_os_str() {
+			continue;
+		}
+
+		let target = link_path.join(entry.file_name());
+
+		if std::fs::symlink_metadata(&target).is_ok_and(|m| m.file_type().is_symlink()) {
+			std::fs::remove_file(&target)?;
+		}
+
+		super::symlink_any(&entry.path(), &target)?;
+	}
+
+	Ok(())
+}
+
+/// A difference between a workspace's configured links
+/// and the filesystem
+#[non_exhaustive]
+pub enum LinkProblem {
+	/// The linked repository can't be loaded
+	Unloadable(String, Name),
+
+	/// The link is not on disk
+	Missing(String),
+
+	/// The symlink points at something that doesn't exist
+	Dangling(String),
+
+	/// The symlink points somewhere other than the
+	/// repository
+	WrongTarget(String, PathBuf),
+
+	/// Something other than the link is in the way
+	Obstructed(String),
+
+	/// An entry that isn't a configured link, and whether
+	/// it is a symlink
+	Untracked(String, bool)
+}
+
+impl Display for LinkProblem {
+	#[inline]
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+		match self {
+			Self::Unloadable(link, repository) => write!(f, "{link}: repository {repository} could not be loaded"),
+			Self::Missing(link) => write!(f, "{link}: link is missing"),
+			Self::Dangling(link) => write!(f, "{link}: symlink is dangling"),
+			Self::WrongTarget(link, target) => write!(f, "{link}: symlink points to {}", target.display()),
+			Self::Obstructed(link) => write!(f, "{link}: something else is in the way"),
+			Self::Untracked(name, true) => write!(f, "{name}: untracked symlink"),
+			Self::Untracked(name, false) => write!(f, "{name}: untracked entry")
+		}
+	}
 }
 
 impl Resource for Workspace {
@@ -225,13 +969,27 @@ impl Resource for Workspace {
 	}
 }
 
+impl ScriptHost for Workspace {
+	#[inline]
+	fn scripts(&self) -> HashMap<String, Script> {
+		self.config.scripts.clone()
+	}
+
+	/// Workspaces are cached apart from repositories, since
+	/// they can share names
+	#[inline]
+	fn cache_key(&self) -> PathBuf {
+		PathBuf::from("workspaces").join(PathBuf::from(&self.config.name))
+	}
+}
+
 #[derive(Clone)]
 #[non_exhaustive]
 pub struct Config {
 	pub name: Name,
 	pub version: Version,
-	pub links: HashMap<String, Name>,
-	pub scripts: HashMap<String, String>,
+	pub links: HashMap<String, Link>,
+	pub scripts: HashMap<String, Script>,
 	pub dependencies: HashMap<Name, String>
 }
 
@@ -273,14 +1031,36 @@ impl From<AnyTomlConfig> for TomlConfigLatest {
 
 // CONFIG VERSIONS //
 pub type TomlConfigLatest = TomlConfig0_2_2;
+pub type ManifestLatest = Manifest0_2_2;
+
+/// A portable description of a workspace, along with
+/// where to get the repositories it links
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Manifest0_2_2 {
+	pub environment: tomlconfig::Environment0_2_2,
+	pub config: TomlConfig0_2_2,
+	#[serde(serialize_with = "tomlconfig::sorted_map")]
+	pub repositories: Option<HashMap<Name, ManifestRepository0_2_2>>
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ManifestRepository0_2_2 {
+	pub version: Option<Version>,
+	pub git: Option<tomlconfig::RepositoryGit0_2_2>
+}
 
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
 #[non_exhaustive]
 pub struct TomlConfig0_2_2 {
 	pub environment: tomlconfig::Environment0_2_2,
 	pub workspace: tomlconfig::Workspace0_2_2,
+	#[serde(serialize_with = "tomlconfig::sorted_map")]
 	pub links: Option<tomlconfig::Links0_2_2>,
+	#[serde(serialize_with = "tomlconfig::sorted_map")]
 	pub scripts: Option<tomlconfig::Scripts0_2_2>,
+	#[serde(serialize_with = "tomlconfig::sorted_map")]
 	pub dependencies: Option<tomlconfig::Dependencies0_2_2>
 }
 
@@ -303,8 +1083,8 @@ impl From<TomlConfig0_2_1> for TomlConfigLatest {
 				name: value.repository.name,
 				version: value.repository.version
 			},
-			links: value.workspace,
-			scripts: value.scripts,
+			links: value.workspace.map(tomlconfig::upgrade_links_0_2_1),
+			scripts: value.scripts.map(tomlconfig::upgrade_scripts_0_2_1),
 			dependencies: value.dependencies
 		}
 	}
@@ -329,8 +1109,8 @@ impl From<TomlConfig0_2_0> for TomlConfigLatest {
 				name: value.repository.name,
 				version: value.repository.version
 			},
-			links: value.workspace,
-			scripts: value.scripts,
+			links: value.workspace.map(tomlconfig::upgrade_links_0_2_1),
+			scripts: value.scripts.map(tomlconfig::upgrade_scripts_0_2_1),
 			dependencies: value.dependencies
 		}
 	}
@@ -342,8 +1122,16 @@ impl From<TomlConfig0_2_2> for Config {
 		Self {
 			name: value.workspace.name,
 			version: value.workspace.version,
-			links: value.links.unwrap_or_default(),
-			scripts: value.scripts.unwrap_or_default(),
+			links: value.links
+				.unwrap_or_default()
+				.into_iter()
+				.map(|(k, v)| (k, v.into()))
+				.collect(),
+			scripts: value.scripts
+				.unwrap_or_default()
+				.into_iter()
+				.map(|(k, v)| (k, v.into()))
+				.collect(),
 			dependencies: value.dependencies.unwrap_or_default()
 		}
 	}
@@ -358,8 +1146,12 @@ impl From<Config> for TomlConfigLatest {
 				name: value.name,
 				version: value.version
 			},
-			links: tomlconfig::hashmap_to_option_hashmap(value.links),
-			scripts: tomlconfig::hashmap_to_option_hashmap(value.scripts),
+			links: tomlconfig::hashmap_to_option_hashmap(
+				value.links.into_iter().map(|(k, v)| (k, v.into())).collect()
+			),
+			scripts: tomlconfig::hashmap_to_option_hashmap(
+				value.scripts.into_iter().map(|(k, v)| (k, v.into())).collect()
+			),
 			dependencies: tomlconfig::hashmap_to_option_hashmap(value.dependencies)
 		}
 	}
@@ -0,0 +1,893 @@
+use batl::resource::{Repository, Workspace};
+use batl::resource::cache::ScriptCache;
+use batl::resource::history::RunLog;
+use batl::resource::script::{Hook, Script, ScriptHost};
+use colored::*;
+use crate::output::*;
+use crate::utils::UtilityError;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader, IsTerminal, Read};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+
+/// Options for running a script
+#[derive(Default)]
+pub struct RunOptions<'a> {
+	/// Arguments passed to the named script, but not its
+	/// dependencies
+	pub args: &'a [String],
+
+	/// The environment scripts run with
+	pub env: HashMap<String, String>,
+
+	/// Prefix for every line of output. Output is passed
+	/// straight through if this is `None`.
+	pub prefix: Option<String>,
+
+	/// Where to record everything the run prints
+	pub log: Option<&'a RunLog>,
+
+	/// The folder scripts run in, if not the repository's
+	/// own, such as for links to a folder inside one
+	pub cwd: Option<PathBuf>
+}
+
+/// Why a script or command failed
+#[derive(Debug)]
+pub enum ScriptFailure {
+	/// Exited with a non-zero code
+	Exit(i32),
+
+	/// Killed by a signal
+	Signal(i32),
+
+	/// Ran past its timeout and was killed
+	TimedOut(Duration)
+}
+
+impl ScriptFailure {
+	/// Get the failure for an exit status, if it failed
+	pub fn from_status(status: ExitStatus) -> Option<Self> {
+		if status.success() {
+			return None;
+		}
+
+		#[cfg(unix)]
+		{
+			use std::os::unix::process::ExitStatusExt;
+
+			if let Some(signal) = status.signal() {
+				return Some(Self::Signal(signal));
+			}
+		}
+
+		Some(Self::Exit(status.code().unwrap_or(1)))
+	}
+}
+
+impl Display for ScriptFailure {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Exit(code) => write!(f, "Exit code {}", code),
+			Self::Signal(signal) => write!(f, "Killed by signal {}", signal),
+			Self::TimedOut(timeout) => write!(f, "Timed out after {}s", timeout.as_secs())
+		}
+	}
+}
+
+/// Get the environment for scripts in a repository,
+/// including workspace variables if it is run as a link
+pub fn script_env(repository: &Repository, workspace: Option<&Workspace>) -> HashMap<String, String> {
+	let mut env = repository.script_env();
+
+	if let Some(workspace) = workspace {
+		env.extend(workspace.script_env());
+	}
+
+	env
+}
+
+/// Run a repository script, running every script it
+/// depends on first. Names of Lua files in the repository
+/// run in the embedded Lua runtime instead.
+pub fn run_script(repository: &Repository, name: &str, options: &RunOptions) -> Result<(), UtilityError> {
+	if let Some(path) = crate::lua::script_path(repository, name) {
+		return crate::lua::run(repository, &path, options);
+	}
+
+	run_host_script(repository, name, options)
+}
+
+/// Run a script in a repository or workspace, running
+/// every script it depends on first
+pub fn run_host_script<H: ScriptHost>(host: &H, name: &str, options: &RunOptions) -> Result<(), UtilityError> {
+	let order = host.script_order(name)?;
+
+	for script_name in order {
+		let script = host.script(&script_name)
+			.ok_or(UtilityError::ScriptNotFound(script_name.clone()))?;
+
+		let script_args = if script_name == name { options.args } else { &[] };
+
+		if !script_args.is_empty() && script.ignores_arguments() {
+			caution(options, &format!(
+				"{} doesn't use its arguments, ignoring {}",
+				script_name,
+				shell_words::join(script_args)
+			));
+		}
+
+		let root = options.cwd.as_deref().unwrap_or(host.path());
+
+		let Some(mut process) = script.process(root, script_args, &options.env)? else {
+			continue;
+		};
+
+		let cache = cached_run(host, &script, &script_name, script_args, &options.env)?;
+
+		if let Some((cache, hash)) = &cache {
+			if cache.is_fresh(hash) {
+				let restored = cache.restore_outputs(host, &script)?;
+
+				announce(options, &format!("Skipping {}, inputs unchanged", script_name));
+
+				if !restored.is_empty() {
+					announce(options, &format!("Restored outputs {}", restored.join(", ")));
+				}
+
+				continue;
+			}
+		}
+
+		if script_name != name {
+			announce(options, &format!("Running dependency {}", script_name));
+		}
+
+		let mut attempt = 0;
+
+		let failure = loop {
+			let failure = run_process(&mut process, options.prefix.as_deref(), options.log, script.timeout)?;
+
+			match failure {
+				Some(failure) if attempt < script.retries => {
+					attempt += 1;
+
+					announce(options, &format!(
+						"{} failed ({}), retrying {} of {}",
+						script_name, failure, attempt, script.retries
+					));
+				},
+				_ => break failure
+			}
+		};
+
+		if let Some(failure) = failure {
+			if !script.allow_failure {
+				return Err(UtilityError::ScriptFailed(script_name, failure));
+			}
+
+			announce(options, &format!("{} failed ({}), failure allowed", script_name, failure));
+
+			continue;
+		}
+
+		if let Some((cache, hash)) = &cache {
+			cache.store(hash, host, &script)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Run a script like `run_script`, recording the run and
+/// its output under `gen/logs`. `target` is the name of
+/// the repository or link it runs in.
+pub fn run_logged(repository: &Repository, target: &str, name: &str, options: &RunOptions) -> Result<(), UtilityError> {
+	logged(repository, target, name, options, |options| run_script(repository, name, options))
+}
+
+/// Run a script with `run`, recording the run and its
+/// output under `gen/logs`
+pub fn logged<H, F>(host: &H, target: &str, name: &str, options: &RunOptions, run: F) -> Result<(), UtilityError>
+where
+	H: ScriptHost,
+	F: FnOnce(&RunOptions) -> Result<(), UtilityError>
+{
+	let command = host.script(name).and_then(|script| script.command);
+	let log = RunLog::create(target.to_string(), Some(name.to_string()), command)?;
+
+	let options = RunOptions {
+		args: options.args,
+		env: options.env.clone(),
+		prefix: options.prefix.clone(),
+		log: log.as_ref(),
+		cwd: options.cwd.clone()
+	};
+
+	let result = run(&options);
+
+	if let Some(log) = log {
+		let status = match &result {
+			Ok(()) => "Success".to_string(),
+			Err(err) => err.to_string()
+		};
+
+		log.finish(result.is_ok(), status)?;
+	}
+
+	result
+}
+
+/// Get the cache and current input hash for a script,
+/// if it declares any inputs
+fn cached_run<H: ScriptHost>(
+	host: &H,
+	script: &Script,
+	name: &str,
+	args: &[String],
+	env: &HashMap<String, String>
+) -> Result<Option<(ScriptCache, String)>, UtilityError> {
+	if script.inputs.is_empty() {
+		return Ok(None);
+	}
+
+	let Some(cache) = ScriptCache::new(host, name) else {
+		return Ok(None);
+	};
+
+	let hash = ScriptCache::input_hash(host, script, args, env)?;
+
+	Ok(Some((cache, hash)))
+}
+
+/// Print a status message, matching the output style
+/// of the run
+fn announce(options: &RunOptions, message: &str) {
+	if let Some(log) = options.log {
+		log.line(message);
+	}
+
+	match &options.prefix {
+		Some(prefix) => println!("{} {}", prefix, message),
+		None => info(&format!("{}\n", message))
+	}
+}
+
+/// Print a warning, matching the output style of the run
+fn caution(options: &RunOptions, message: &str) {
+	if let Some(log) = options.log {
+		log.line(message);
+	}
+
+	match &options.prefix {
+		Some(prefix) => println!("{} {} {}", prefix, "warning:".yellow(), message),
+		None => warning(&format!("{}\n", message))
+	}
+}
+
+/// Run a process to completion, returning why it failed
+/// if it did. Output is prefixed if there is a prefix,
+/// and copied to the log if there is one. Without a
+/// prefix, output going to a terminal is left alone so
+/// the process can still use it, and isn't logged.
+///
+/// With a timeout, the process runs in its own process
+/// group so that anything it starts is killed along with
+/// it when time runs out. Interrupts are forwarded to the
+/// group, and the group is killed if the run is cut short.
+/// Being out of the terminal's foreground group, it gets
+/// no input, since reading would stop it.
+pub fn run_process(
+	process: &mut Command,
+	prefix: Option<&str>,
+	log: Option<&RunLog>,
+	timeout: Option<Duration>
+) -> Result<Option<ScriptFailure>, std::io::Error> {
+	#[cfg(unix)]
+	if timeout.is_some() {
+		use std::os::unix::process::CommandExt;
+
+		process
+			.process_group(0)
+			.stdin(Stdio::null());
+	}
+
+	let capture_stdout = prefix.is_some() || (log.is_some() && !std::io::stdout().is_terminal());
+	let capture_stderr = prefix.is_some() || (log.is_some() && !std::io::stderr().is_terminal());
+
+	if capture_stdout {
+		process.stdout(Stdio::piped());
+	}
+
+	if capture_stderr {
+		process.stderr(Stdio::piped());
+	}
+
+	if let Some(log) = log.filter(|_| !capture_stdout || !capture_stderr) {
+		log.line("[Output shown on the terminal is not recorded]");
+	}
+
+	let mut child = process.spawn()?;
+
+	let _group = timeout.map(|_| ProcessGroup::register(&child));
+
+	let stdout = child.stdout.take();
+	let stderr = child.stderr.take();
+
+	let status = std::thread::scope(|scope| {
+		if let Some(stdout) = stdout {
+			scope.spawn(move || forward_lines(stdout, prefix, log, false));
+		}
+
+		if let Some(stderr) = stderr {
+			scope.spawn(move || forward_lines(stderr, prefix, log, true));
+		}
+
+		wait_timeout(&mut child, timeout)
+	})?;
+
+	Ok(match (status, timeout) {
+		(Some(status), _) => ScriptFailure::from_status(status),
+		(None, Some(timeout)) => Some(ScriptFailure::TimedOut(timeout)),
+		(None, None) => None
+	})
+}
+
+/// Wait for a process to exit, killing it if it runs
+/// past the timeout. Returns `None` if it was killed.
+fn wait_timeout(child: &mut Child, timeout: Option<Duration>) -> Result<Option<ExitStatus>, std::io::Error> {
+	let Some(timeout) = timeout else {
+		return child.wait().map(Some);
+	};
+
+	let deadline = Instant::now() + timeout;
+
+	loop {
+		if let Some(status) = child.try_wait()? {
+			return Ok(Some(status));
+		}
+
+		let now = Instant::now();
+
+		if now >= deadline {
+			kill_group(child)?;
+			child.wait()?;
+
+			return Ok(None);
+		}
+
+		std::thread::sleep((deadline - now).min(Duration::from_millis(50)));
+	}
+}
+
+/// Kill a process along with its process group
+fn kill_group(child: &mut Child) -> Result<(), std::io::Error> {
+	#[cfg(unix)]
+	if let Ok(pid) = libc::pid_t::try_from(child.id()) {
+		// The child leads its own group, so a negative pid
+		// signals everything it started too
+		if unsafe { libc::kill(-pid, libc::SIGKILL) } == 0 {
+			return Ok(());
+		}
+	}
+
+	child.kill()
+}
+
+/// Process groups of running scripts, which interrupts
+/// are forwarded to. Empty slots are zero.
+#[cfg(unix)]
+static PROCESS_GROUPS: [std::sync::atomic::AtomicI32; 64] = [const { std::sync::atomic::AtomicI32::new(0) }; 64];
+
+/// A script running in its own process group. The group
+/// receives any interrupt sent to batl while it runs, and
+/// is killed when this is dropped.
+struct ProcessGroup {
+	#[cfg(unix)]
+	pid: libc::pid_t
+}
+
+impl ProcessGroup {
+	#[cfg(unix)]
+	fn register(child: &Child) -> Self {
+		use std::sync::atomic::Ordering;
+
+		static HANDLER: std::sync::Once = std::sync::Once::new();
+
+		HANDLER.call_once(|| {
+			let handler = forward_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+
+			for signal in [libc::SIGINT, libc::SIGTERM] {
+				// Signals batl was started ignoring stay ignored
+				unsafe {
+					if libc::signal(signal, handler) == libc::SIG_IGN {
+						libc::signal(signal, libc::SIG_IGN);
+					}
+				}
+			}
+		});
+
+		let pid = libc::pid_t::try_from(child.id()).unwrap_or(0);
+
+		if pid != 0 {
+			// With every slot taken, the group is still killed
+			// on drop, it just doesn't see interrupts
+			let _ = PROCESS_GROUPS
+				.iter()
+				.find(|slot| slot.compare_exchange(0, pid, Ordering::SeqCst, Ordering::SeqCst).is_ok());
+		}
+
+		Self { pid }
+	}
+
+	#[cfg(not(unix))]
+	fn register(_child: &Child) -> Self {
+		Self {}
+	}
+}
+
+impl Drop for ProcessGroup {
+	fn drop(&mut self) {
+		#[cfg(unix)]
+		if self.pid != 0 {
+			use std::sync::atomic::Ordering;
+
+			for slot in &PROCESS_GROUPS {
+				let _ = slot.compare_exchange(self.pid, 0, Ordering::SeqCst, Ordering::SeqCst);
+			}
+
+			// Anything still running in the group outlived its
+			// script, whether it finished or not
+			unsafe { libc::kill(-self.pid, libc::SIGKILL) };
+		}
+	}
+}
+
+/// Signal handler passing interrupts on to every running
+/// process group. With none running, batl is interrupted
+/// as usual.
+#[cfg(unix)]
+extern "C" fn forward_signal(signal: libc::c_int) {
+	use std::sync::atomic::Ordering;
+
+	let mut forwarded = false;
+
+	for slot in &PROCESS_GROUPS {
+		let pid = slot.load(Ordering::SeqCst);
+
+		if pid != 0 {
+			unsafe { libc::kill(-pid, signal) };
+			forwarded = true;
+		}
+	}
+
+	if !forwarded {
+		unsafe {
+			libc::signal(signal, libc::SIG_DFL);
+			libc::raise(signal);
+		}
+	}
+}
+
+/// Print each line from a reader with an optional prefix,
+/// copying it to the log
+fn forward_lines<R: Read>(reader: R, prefix: Option<&str>, log: Option<&RunLog>, stderr: bool) {
+	for line in BufReader::new(reader).lines().map_while(Result::ok) {
+		output_line(prefix, log, &line, stderr);
+	}
+}
+
+/// Print a line of script output with an optional prefix,
+/// copying it to the log
+pub fn output_line(prefix: Option<&str>, log: Option<&RunLog>, line: &str, stderr: bool) {
+	if let Some(log) = log {
+		log.line(line);
+	}
+
+	let line = match prefix {
+		Some(prefix) => format!("{} {}", prefix, line),
+		None => line.to_string()
+	};
+
+	if stderr {
+		eprintln!("{}", line);
+	} else {
+		println!("{}", line);
+	}
+}
+
+/// Run every script registered for a lifecycle hook
+pub fn run_hook(repository: &Repository, hook: Hook) -> Result<(), UtilityError> {
+	let options = RunOptions {
+		env: script_env(repository, None),
+		..Default::default()
+	};
+
+	for script in repository.hook_scripts(hook) {
+		info(&format!("Running {} hook {}\n", hook, script));
+
+		run_script(repository, &script, &options)
+			.map_err(|err| UtilityError::HookFailed(hook.to_string(), Box::new(err)))?;
+
+		println!();
+	}
+
+	Ok(())
+}
+
+/// Print every script in a repository or workspace along
+/// with its description
+pub fn list_scripts<H: ScriptHost>(host: &H) {
+	let scripts = host.scripts();
+
+	let mut names: Vec<&String> = scripts.keys().collect();
+	names.sort();
+
+	for name in names {
+		let description = scripts.get(name)
+			.and_then(|script| script.description.clone())
+			.unwrap_or_default();
+
+		println!("{}:\t\t{}", name, description);
+	}
+}
+
+/// The result of running a script in a single link
+pub enum LinkOutcome {
+	Passed,
+	Failed(String),
+	Skipped(String)
+}
+
+/// Run a script in several workspace links at once, using
+/// up to `jobs` threads. A link only starts once every
+/// link it depends on has finished, and if one fails,
+/// everything downstream of it is skipped. Links that
+/// don't have the script are skipped without blocking
+/// their dependents. Results are returned in the order
+/// of `links`.
+///
+/// `depends` maps each link to the links it depends on,
+/// dependencies outside of `links` are ignored.
+pub fn run_in_links(
+	workspace: &Workspace,
+	links: &[String],
+	depends: &HashMap<String, Vec<String>>,
+	script: &str,
+	args: &[String],
+	jobs: usize
+) -> Result<Vec<(String, LinkOutcome)>, UtilityError> {
+	let width = links.iter().map(String::len).max().unwrap_or_default();
+	let schedule = Schedule::new(links, depends)?;
+
+	let state = Mutex::new(schedule);
+	let changed = Condvar::new();
+
+	std::thread::scope(|scope| {
+		for _ in 0..jobs.max(1) {
+			scope.spawn(|| {
+				loop {
+					let link = {
+						let mut schedule = state.lock().unwrap();
+
+						loop {
+							if let Some(link) = schedule.ready.pop_front() {
+								schedule.running += 1;
+								break Some(link);
+							}
+
+							if schedule.running == 0 {
+								break None;
+							}
+
+							schedule = changed.wait(schedule).unwrap();
+						}
+					};
+
+					let Some(link) = link else {
+						changed.notify_all();
+						break;
+					};
+
+					let outcome = run_in_link(workspace, &link, script, args, width);
+
+					state.lock().unwrap().finish(link, outcome);
+					changed.notify_all();
+				}
+			});
+		}
+	});
+
+	let mut results = state.into_inner().unwrap().results;
+
+	Ok(links.iter()
+		.filter_map(|link| Some((link.clone(), results.remove(link)?)))
+		.collect())
+}
+
+/// Tracks which links are ready to run for `run_in_links`
+struct Schedule {
+	/// Links that can run now
+	ready: VecDeque<String>,
+
+	/// Number of unfinished dependencies for each
+	/// waiting link
+	waiting: HashMap<String, usize>,
+
+	/// Links that depend on each link
+	dependents: HashMap<String, Vec<String>>,
+
+	/// Number of links currently running
+	running: usize,
+
+	/// Finished links
+	results: HashMap<String, LinkOutcome>
+}
+
+impl Schedule {
+	fn new(links: &[String], depends: &HashMap<String, Vec<String>>) -> Result<Self, UtilityError> {
+		let mut waiting = HashMap::new();
+		let mut dependents: HashMap<String, Vec<String>> = HashMap::new();
+
+		for link in links {
+			let link_depends: Vec<&String> = depends.get(link)
+				.map(|d| d.iter().filter(|dep| links.contains(dep)).collect())
+				.unwrap_or_default();
+
+			for dep in &link_depends {
+				dependents.entry((*dep).clone()).or_default().push(link.clone());
+			}
+
+			waiting.insert(link.clone(), link_depends.len());
+		}
+
+		let mut schedule = Self {
+			ready: VecDeque::new(),
+			waiting,
+			dependents,
+			running: 0,
+			results: HashMap::new()
+		};
+
+		schedule.check_cycles()?;
+
+		for link in links {
+			if schedule.waiting.get(link) == Some(&0) {
+				schedule.waiting.remove(link);
+				schedule.ready.push_back(link.clone());
+			}
+		}
+
+		Ok(schedule)
+	}
+
+	/// Make sure every link can eventually run
+	fn check_cycles(&self) -> Result<(), UtilityError> {
+		let mut waiting = self.waiting.clone();
+		let mut ready: Vec<String> = waiting.iter()
+			.filter(|(_, count)| **count == 0)
+			.map(|(link, _)| link.clone())
+			.collect();
+
+		while let Some(link) = ready.pop() {
+			waiting.remove(&link);
+
+			for dependent in self.dependents.get(&link).into_iter().flatten() {
+				if let Some(count) = waiting.get_mut(dependent) {
+					*count -= 1;
+
+					if *count == 0 {
+						ready.push(dependent.clone());
+					}
+				}
+			}
+		}
+
+		if waiting.is_empty() {
+			Ok(())
+		} else {
+			let mut cycle: Vec<String> = waiting.into_keys().collect();
+			cycle.sort();
+
+			Err(UtilityError::ScriptCycle(format!("links {}", cycle.join(", "))))
+		}
+	}
+
+	/// Record a finished link, releasing or skipping
+	/// everything that depends on it
+	fn finish(&mut self, link: String, outcome: LinkOutcome) {
+		self.running -= 1;
+
+		let dependents = self.dependents.get(&link).cloned().unwrap_or_default();
+
+		if let LinkOutcome::Failed(_) = outcome {
+			self.skip_dependents(&link, &link);
+		} else {
+			for dependent in dependents {
+				let Some(count) = self.waiting.get_mut(&dependent) else {
+					continue;
+				};
+
+				*count -= 1;
+
+				if *count == 0 {
+					self.waiting.remove(&dependent);
+					self.ready.push_back(dependent);
+				}
+			}
+		}
+
+		self.results.insert(link, outcome);
+	}
+
+	/// Skip every link downstream of a failed link
+	fn skip_dependents(&mut self, link: &str, failed: &str) {
+		for dependent in self.dependents.get(link).cloned().unwrap_or_default() {
+			if self.waiting.remove(&dependent).is_some() {
+				self.results.insert(
+					dependent.clone(),
+					LinkOutcome::Skipped(format!("Dependency {} failed", failed))
+				);
+
+				self.skip_dependents(&dependent, failed);
+			}
+		}
+	}
+}
+
+/// Run a script in a single link for `run_in_links`
+fn run_in_link(workspace: &Workspace, link: &str, script: &str, args: &[String], width: usize) -> LinkOutcome {
+	let Some(repository) = workspace.link(link) else {
+		return LinkOutcome::Failed("Repository could not be loaded".to_string());
+	};
+
+	if !has_script(&repository, script) {
+		return LinkOutcome::Skipped(format!("No script {}", script));
+	}
+
+	let options = RunOptions {
+		args,
+		env: script_env(&repository, Some(workspace)),
+		prefix: Some(format!("{:width$} |", link).cyan().to_string()),
+		cwd: workspace.link_cwd(link),
+		..Default::default()
+	};
+
+	match run_logged(&repository, link, script, &options) {
+		Ok(()) => LinkOutcome::Passed,
+		Err(err) => LinkOutcome::Failed(err.to_string())
+	}
+}
+
+/// Whether a repository defines a script, either in its
+/// config or as a Lua file.
+pub fn has_script(repository: &Repository, script: &str) -> bool {
+	repository.script(script).is_some() || crate::lua::script_path(repository, script).is_some()
+}
+
+/// Print a summary of a multi-link run. Returns an error
+/// if any link failed, or if every link was skipped.
+pub fn summarize(results: &[(String, LinkOutcome)]) -> Result<(), UtilityError> {
+	println!();
+
+	let mut failed = 0;
+	let mut skipped = 0;
+
+	for (link, outcome) in results {
+		match outcome {
+			LinkOutcome::Passed => success(link),
+			LinkOutcome::Failed(reason) => {
+				failed += 1;
+				error(&format!("{}: {}", link, reason));
+			},
+			LinkOutcome::Skipped(reason) => {
+				skipped += 1;
+				println!("[{}] {}: {}", "SKIP".yellow(), link, reason);
+			}
+		}
+	}
+
+	if failed > 0 {
+		return Err(UtilityError::ScriptError(format!("{} of {} links failed", failed, results.len())));
+	}
+
+	if skipped > 0 && skipped == results.len() {
+		return Err(UtilityError::ScriptError(format!("Skipped in all {} links", skipped)));
+	}
+
+	Ok(())
+}
+
+/// The default number of parallel jobs
+pub fn default_jobs() -> usize {
+	std::thread::available_parallelism()
+		.map(usize::from)
+		.unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Run a schedule one link at a time, returning the
+	/// order links ran in and every result
+	fn drive(
+		links: &[&str],
+		depends: &[(&str, &[&str])],
+		outcome: impl Fn(&str) -> LinkOutcome
+	) -> (Vec<String>, HashMap<String, LinkOutcome>) {
+		let links: Vec<String> = links.iter().map(ToString::to_string).collect();
+		let depends: HashMap<String, Vec<String>> = depends
+			.iter()
+			.map(|(link, deps)| (link.to_string(), deps.iter().map(ToString::to_string).collect()))
+			.collect();
+
+		let mut schedule = Schedule::new(&links, &depends).unwrap();
+		let mut ran = Vec::new();
+
+		while let Some(link) = schedule.ready.pop_front() {
+			schedule.running += 1;
+			ran.push(link.clone());
+
+			let result = outcome(&link);
+			schedule.finish(link, result);
+		}
+
+		(ran, schedule.results)
+	}
+
+	#[test]
+	fn runs_dependencies_first() {
+		let (ran, _) = drive(&["app", "lib", "core"], &[("app", &["lib"]), ("lib", &["core"])], |_| LinkOutcome::Passed);
+
+		assert_eq!(ran, ["core", "lib", "app"]);
+	}
+
+	#[test]
+	fn skips_downstream_of_failure() {
+		let (ran, results) = drive(
+			&["app", "lib", "core", "other"],
+			&[("app", &["lib"]), ("lib", &["core"])],
+			|link| if link == "core" { LinkOutcome::Failed("Exit code 1".to_string()) } else { LinkOutcome::Passed }
+		);
+
+		assert_eq!(ran, ["core", "other"]);
+		assert!(matches!(results.get("lib"), Some(LinkOutcome::Skipped(reason)) if reason == "Dependency core failed"));
+		assert!(matches!(results.get("app"), Some(LinkOutcome::Skipped(reason)) if reason == "Dependency core failed"));
+		assert!(matches!(results.get("other"), Some(LinkOutcome::Passed)));
+	}
+
+	#[test]
+	fn skipped_links_release_dependents() {
+		let (ran, results) = drive(
+			&["app", "lib"],
+			&[("app", &["lib"])],
+			|link| if link == "lib" { LinkOutcome::Skipped("No script build".to_string()) } else { LinkOutcome::Passed }
+		);
+
+		assert_eq!(ran, ["lib", "app"]);
+		assert!(matches!(results.get("app"), Some(LinkOutcome::Passed)));
+	}
+
+	#[test]
+	fn ignores_dependencies_outside_the_run() {
+		let (ran, _) = drive(&["app"], &[("app", &["lib"])], |_| LinkOutcome::Passed);
+
+		assert_eq!(ran, ["app"]);
+	}
+
+	#[test]
+	fn rejects_cycles() {
+		let links = vec!["a".to_string(), "b".to_string()];
+		let depends = HashMap::from([
+			("a".to_string(), vec!["b".to_string()]),
+			("b".to_string(), vec!["a".to_string()])
+		]);
+
+		assert!(matches!(Schedule::new(&links, &depends), Err(UtilityError::ScriptCycle(_))));
+	}
+}
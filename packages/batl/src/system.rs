@@ -1,4 +1,5 @@
-use crate::resource::batlrc::BatlRcLatest;
+use crate::resource::batlrc::{self, AnyBatlRc, BatlRcLatest, CredentialsLatest};
+use crate::resource::tomlconfig::TomlConfig;
 use std::env::var as env_var;
 use std::path::PathBuf;
 
@@ -76,6 +77,75 @@ pub fn batlrc_path() -> Option<PathBuf> {
 #[inline]
 #[must_use]
 pub fn batlrc() -> Option<BatlRcLatest> {
-	let config_str = std::fs::read_to_string(batlrc_path()?).ok()?;
-	toml::from_str(&config_str).ok()
+	any_batlrc().map(BatlRcLatest::from)
+}
+
+/// Get the battalion RC config in whatever version
+/// it was written in
+#[inline]
+#[must_use]
+pub fn any_batlrc() -> Option<AnyBatlRc> {
+	AnyBatlRc::read_toml(&batlrc_path()?).ok()
+}
+
+/// Get the battalion credentials path
+#[inline]
+#[must_use]
+pub fn credentials_path() -> Option<PathBuf> {
+	batl_root().map(|p| p.join(".credentials"))
+}
+
+/// Get the stored registry credentials
+#[inline]
+#[must_use]
+pub fn credentials() -> Option<CredentialsLatest> {
+	CredentialsLatest::read_toml(&credentials_path()?).ok()
+}
+
+/// Convert a name into something usable in an environment
+/// variable, such as `my-link` into `MY_LINK`
+#[inline]
+#[must_use]
+pub fn env_var_name(name: &str) -> String {
+	name
+		.chars()
+		.map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+		.collect()
+}
+
+/// Get the environment variable that overrides the token
+/// for a specific registry
+#[inline]
+#[must_use]
+pub fn registry_token_var(registry: &str) -> String {
+	format!("BATL_REGISTRY_{}_TOKEN", env_var_name(registry))
+}
+
+/// Get the API token for a registry. Checked in order:
+///
+/// 1. `BATL_REGISTRY_<NAME>_TOKEN` environment variable
+/// 2. `BATL_TOKEN` environment variable, default registry only
+/// 3. The credentials file
+/// 4. A 0.2.1 batlrc's key, default registry only
+///
+/// The placeholder key is never returned.
+#[inline]
+#[must_use]
+pub fn registry_token(registry: &str) -> Option<String> {
+	let any_batlrc = any_batlrc();
+	let legacy_credentials = any_batlrc.as_ref().and_then(AnyBatlRc::legacy_credentials);
+	let default_registry = any_batlrc
+		.map_or_else(|| batlrc::DEFAULT_REGISTRY.to_owned(), |rc| BatlRcLatest::from(rc).api.registry);
+
+	env_var(registry_token_var(registry)).ok()
+		.or_else(|| {
+			if registry == default_registry {
+				env_var("BATL_TOKEN").ok()
+			} else {
+				None
+			}
+		})
+		.or_else(|| credentials()?.tokens.get(registry).cloned())
+		.or_else(|| legacy_credentials.filter(|_| registry == default_registry))
+		.filter(|token| !token.is_empty() && token != batlrc::PLACEHOLDER_CREDENTIALS)
 }
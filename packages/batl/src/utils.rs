@@ -26,16 +26,36 @@ pub enum UtilityError {
 	LinkNotFound,
 	#[error("Invalid name: {0}")]
 	InvalidName(String),
+	#[error("Invalid path: {0}")]
+	InvalidPath(String),
 	#[error("Already setup")]
 	AlreadySetup,
 	#[error("Script not found: {0}")]
 	ScriptNotFound(String),
 	#[error("Script error: {0}")]
 	ScriptError(String),
+	#[error("Script error: {0}: {1}")]
+	ScriptFailed(String, crate::scripts::ScriptFailure),
+	#[error("Script dependency cycle: {0}")]
+	ScriptCycle(String),
+	#[error("{0} hook failed: {1}")]
+	HookFailed(String, Box<UtilityError>),
+	#[error("Unresolved workspace problems: {0}")]
+	WorkspaceProblems(usize),
 	#[error("Resource cannot be collected: {0}")]
 	ResourceNotCollected(String),
+	#[error("Not authenticated with registry {0}, run `batl auth login`")]
+	NotAuthenticated(String),
 	#[error("Network Error: {0}")]
 	NetworkError(#[from] ureq::Error),
+	#[error("Git error: {0}")]
+	GitError(#[from] git2::Error),
+	#[error("Repository has no git checkout: {0}")]
+	NoGitCheckout(String),
+	#[error("Worktree has uncommitted changes")]
+	UncommittedChanges,
+	#[error("Git update failed for {0} links")]
+	GitUpdateFailed(usize),
 	#[error("Unknown")]
 	Unknown
 }
@@ -67,6 +87,10 @@ impl From<batlerror::CreateResourceError> for UtilityError {
 			batlerror::CreateResourceError::AlreadyExists => UtilityError::ResourceAlreadyExists("<>".to_string()),
 			batlerror::CreateResourceError::IoError(e) => e.into(),
 			batlerror::CreateResourceError::NotSetup => UtilityError::ResourceAlreadyExists("Battalion root".to_string()),
+			batlerror::CreateResourceError::NoGitCheckout => UtilityError::NoGitCheckout("<>".to_string()),
+			batlerror::CreateResourceError::NoArchive => UtilityError::ResourceDoesNotExist("Archive".to_string()),
+			batlerror::CreateResourceError::InvalidPath(path) => UtilityError::InvalidPath(path),
+			batlerror::CreateResourceError::GitError(e) => e.into(),
 			_ => UtilityError::Unknown
 		}
 	}
@@ -77,6 +101,8 @@ impl From<batlerror::DeleteResourceError> for UtilityError {
 		match value {
 			batlerror::DeleteResourceError::DoesNotExist => UtilityError::ResourceAlreadyExists("<>".to_string()),
 			batlerror::DeleteResourceError::IoError(e) => e.into(),
+			batlerror::DeleteResourceError::UncommittedChanges => UtilityError::UncommittedChanges,
+			batlerror::DeleteResourceError::GitError(e) => e.into(),
 			_ => UtilityError::Unknown
 		}
 	}
@@ -93,6 +119,25 @@ impl From<batlerror::CreateDependentResourceError> for UtilityError {
 	}
 }
 
+impl From<batlerror::ScriptProcessError> for UtilityError {
+	fn from(value: batlerror::ScriptProcessError) -> Self {
+		match value {
+			batlerror::ScriptProcessError::InvalidCwd(cwd) => UtilityError::InvalidPath(cwd),
+			err => UtilityError::ScriptError(err.to_string())
+		}
+	}
+}
+
+impl From<batlerror::ScriptGraphError> for UtilityError {
+	fn from(value: batlerror::ScriptGraphError) -> Self {
+		match value {
+			batlerror::ScriptGraphError::NotFound(name) => UtilityError::ScriptNotFound(name),
+			batlerror::ScriptGraphError::Cycle(cycle) => UtilityError::ScriptCycle(cycle.join(" -> ")),
+			_ => UtilityError::Unknown
+		}
+	}
+}
+
 #[cfg(target_os = "windows")]
 pub fn windows_symlink_perms() -> Result<(), std::io::Error> {
 	let winuser = whoami::username();
@@ -0,0 +1,176 @@
+use batl::resource::{Repository, Resource};
+use batl::resource::script::ScriptHost;
+use clap::Args;
+use crate::output::*;
+use crate::utils::UtilityError;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+
+/// How often the filesystem is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Args, Clone)]
+pub struct WatchArgs {
+	/// Rerun the script whenever files change
+	#[arg(long = "watch")]
+	pub watch: bool,
+
+	/// Milliseconds to wait for changes to settle before
+	/// rerunning
+	#[arg(long = "debounce", default_value_t = 300, requires = "watch")]
+	pub debounce: u64,
+
+	/// Clear the screen before each rerun
+	#[arg(long = "clear", requires = "watch")]
+	pub clear: bool
+}
+
+/// The modification time and size of every watched file
+type Snapshot = HashMap<PathBuf, (Option<SystemTime>, u64)>;
+
+/// A named directory to watch
+pub struct WatchRoot {
+	pub name: String,
+	pub path: PathBuf,
+
+	/// Paths that never count as changes, like script
+	/// outputs
+	pub exclude: Vec<PathBuf>
+}
+
+impl WatchRoot {
+	/// Watch a repository, excluding the outputs of all
+	/// of its scripts
+	pub fn repository(name: String, repository: &Repository) -> Self {
+		let exclude = repository.scripts()
+			.into_values()
+			.flat_map(|script| script.outputs)
+			.map(|output| repository.path().join(output))
+			.collect();
+
+		Self {
+			name,
+			path: repository.path().to_path_buf(),
+			exclude
+		}
+	}
+}
+
+/// Watches several named directories for changes. Files
+/// ignored by `batl.ignore` or `.gitignore` are skipped,
+/// along with hidden files like the `.git` folder.
+pub struct Watcher {
+	roots: Vec<WatchRoot>,
+	snapshots: HashMap<String, Snapshot>,
+	args: WatchArgs
+}
+
+impl Watcher {
+	pub fn new(roots: Vec<WatchRoot>, args: WatchArgs) -> Self {
+		let mut watcher = Self {
+			roots,
+			snapshots: HashMap::new(),
+			args
+		};
+
+		watcher.refresh();
+		watcher
+	}
+
+	/// Run `run` once for everything, then again every time
+	/// something changes with the names of the changed
+	/// roots. Errors are reported, but don't stop the
+	/// watcher.
+	pub fn run<F>(mut self, mut run: F) -> Result<(), UtilityError>
+	where
+		F: FnMut(Option<&[String]>) -> Result<(), UtilityError>
+	{
+		let mut changed: Option<Vec<String>> = None;
+
+		loop {
+			if let Err(err) = run(changed.as_deref()) {
+				error(&err.to_string());
+			}
+
+			// Ignore anything the run itself changed
+			self.refresh();
+
+			println!();
+			info("Watching for changes...");
+
+			changed = Some(self.wait());
+		}
+	}
+
+	/// Block until something changes, then wait for the
+	/// changes to settle. Returns the names of every root
+	/// that changed.
+	fn wait(&mut self) -> Vec<String> {
+		let mut changed = Vec::new();
+
+		while changed.is_empty() {
+			std::thread::sleep(POLL_INTERVAL);
+			changed = self.refresh();
+		}
+
+		loop {
+			std::thread::sleep(Duration::from_millis(self.args.debounce));
+
+			let more = self.refresh();
+
+			if more.is_empty() {
+				break;
+			}
+
+			changed.extend(more);
+		}
+
+		changed.sort();
+		changed.dedup();
+
+		if self.args.clear {
+			let _ = console::Term::stdout().clear_screen();
+		}
+
+		changed
+	}
+
+	/// Take new snapshots, returning the roots that changed
+	fn refresh(&mut self) -> Vec<String> {
+		let mut changed = Vec::new();
+
+		for root in &self.roots {
+			let current = snapshot(&root.path, &root.exclude);
+
+			if self.snapshots.get(&root.name) != Some(&current) {
+				self.snapshots.insert(root.name.clone(), current);
+				changed.push(root.name.clone());
+			}
+		}
+
+		changed
+	}
+}
+
+/// Snapshot every watched file under a directory
+fn snapshot(root: &Path, exclude: &[PathBuf]) -> Snapshot {
+	let exclude = exclude.to_vec();
+
+	let mut walk_builder = ignore::WalkBuilder::new(root);
+	walk_builder
+		.add_custom_ignore_filename("batl.ignore")
+		.require_git(false)
+		.filter_entry(move |entry| !exclude.iter().any(|path| entry.path().starts_with(path)));
+
+	walk_builder.build()
+		.filter_map(Result::ok)
+		.filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+		.filter_map(|entry| {
+			let metadata = entry.metadata().ok()?;
+
+			Some((entry.into_path(), (metadata.modified().ok(), metadata.len())))
+		})
+		.collect()
+}